@@ -1,5 +1,6 @@
 #![allow(unused_imports)]
 use quick_error::quick_error;
+use std::convert::{TryFrom, TryInto};
 
 quick_error! {
     #[derive(Debug)]
@@ -16,6 +17,40 @@ quick_error! {
             display("{}", err)
             from()
         }
+        FromUtf8Error(err: std::string::FromUtf8Error) {
+            display("{}", err)
+            from()
+        }
+        CharTryFromError(err: std::char::CharTryFromError) {
+            display("{}", err)
+            from()
+        }
+        TryFromIntError(err: std::num::TryFromIntError) {
+            display("{}", err)
+            from()
+        }
+        ParseIntError(err: std::num::ParseIntError) {
+            display("{}", err)
+            from()
+        }
+        InvalidDiscriminant(typename: &'static str, discriminant: i32) {
+            display("Invalid discriminant {} for {}", discriminant, typename)
+        }
+        NullHandle(typename: &'static str) {
+            display("Null handle for {}", typename)
+        }
+        NullString(typename: &'static str) {
+            display("Null string for {}", typename)
+        }
+        BorrowedHandle(typename: &'static str) {
+            display("Handle for {} is already borrowed", typename)
+        }
+        ReturnedBorrowedHandle(typename: &'static str) {
+            display("Borrowed handle for {} cannot be returned", typename)
+        }
+        Callback(message: String) {
+            display("{}", message)
+        }
     }
 }
 
@@ -42,8 +77,15 @@ pub trait FromRawConversion: Sized {
 
     /// This method takes ownership of the `raw` object.
     /// Use `PtrAsReference::raw_as_ref` to *not* take ownership of the object.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been created by `IntoRawConversion::into_raw`.
     unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error>;
 
+    /// # Safety
+    ///
+    /// `ptr` must have been created by `IntoRawConversion::into_ptr`.
     unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error>;
 }
 
@@ -54,8 +96,15 @@ pub trait PtrAsReference: Sized {
 
     /// This method does not take ownership of the object pointed to by `raw`.
     /// Use `FromRawConversion::from_raw` to take ownership of the pointer.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to valid data for the duration of the call.
     unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error>;
 
+    /// # Safety
+    ///
+    /// `ptr` must point to valid data for the duration of the call.
     unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error>;
 }
 
@@ -70,6 +119,76 @@ pub unsafe fn box_from_ptr<R, T: FromRawConversion<Raw = R>>(raw: *mut T::Raw) -
     T::from_raw(*Box::from_raw(raw))
 }
 
+/// The status of a generated function which failed with `err`: 3 for errors of callbacks,
+/// which the caller raises again, and 1 for any other `Error`.
+pub fn error_status(err: Error) -> u32 {
+    match err {
+        Error::Callback(_) => 3,
+        _ => 1,
+    }
+}
+
+/// The status of a generated function which unwound with `payload`. Errors of callbacks which
+/// cannot return them unwind as an `Error`, and fail like a returned `Error`. Anything else is
+/// a panic, status 2.
+pub fn unwind_status(payload: Box<dyn std::any::Any + Send>) -> u32 {
+    match payload.downcast::<Error>() {
+        Ok(err) => error_status(*err),
+        Err(_) => 2,
+    }
+}
+
+/// Reference arguments, `&T`. Only handles can be borrowed, since Rust cannot borrow data
+/// held by Lua. Any other type would have to be copied on every call, which is what taking
+/// it by value already does.
+#[diagnostic::on_unimplemented(
+    message = "`&{Self}` cannot be borrowed from Lua",
+    note = "take `{Self}` by value to copy it, or keep it in Rust as a `Handle<{Self}>` and \
+            take `&Handle<{Self}>` to borrow it without copying"
+)]
+pub trait RefArgument {
+    type Ptr: Sized;
+    type Ref: std::ops::Deref<Target = Self>;
+
+    /// # Safety
+    ///
+    /// `ptr` must point to valid data for the duration of the call.
+    unsafe fn ptr_as_deref(ptr: Self::Ptr) -> Result<Self::Ref, Error>;
+}
+
+/// Mutable reference arguments, `&mut T`. Only handles can be mutably borrowed.
+#[diagnostic::on_unimplemented(
+    message = "`&mut {Self}` cannot be passed from Lua",
+    note = "only handles can be mutably borrowed, such as `&mut Handle<T>`"
+)]
+pub trait MutArgument: RefArgument {
+    type Mut: std::ops::DerefMut<Target = Self>;
+
+    /// # Safety
+    ///
+    /// `ptr` must point to valid data for the duration of the call and must not be
+    /// borrowed elsewhere.
+    unsafe fn ptr_as_deref_mut(ptr: Self::Ptr) -> Result<Self::Mut, Error>;
+}
+
+/// The copy of a value type receiving `&self`, owned by the generated function for the
+/// duration of a call.
+pub struct Owned<T>(T);
+
+impl<T> std::ops::Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Owned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
 #[repr(C)]
 pub struct CVec<T> {
     pub ptr: *const T,
@@ -112,7 +231,7 @@ impl<T: FromRawConversion> FromRawConversion for Vec<T> {
     type Ptr = *mut Self::Raw;
 
     unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
-        Vec::from_raw_parts(raw.ptr, raw.len as usize, raw.capacity as usize)
+        Vec::from_raw_parts(raw.ptr, raw.len, raw.capacity)
             .into_iter()
             .map(|value| T::from_raw(value))
             .collect()
@@ -128,7 +247,7 @@ impl<T: PtrAsReference> PtrAsReference for Vec<T> {
     type Ptr = *const Self::Raw;
 
     unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-        std::slice::from_raw_parts(raw.ptr, raw.len as usize)
+        std::slice::from_raw_parts(raw.ptr, raw.len)
             .iter()
             .map(|value| T::raw_as_ref(value))
             .collect()
@@ -139,79 +258,233 @@ impl<T: PtrAsReference> PtrAsReference for Vec<T> {
     }
 }
 
-impl IntoRawConversion for String {
-    type Raw = *mut ::libc::c_char;
-    type Ptr = Self::Raw;
+fn vec_into_array<T, const N: usize>(vec: Vec<T>) -> [T; N] {
+    match vec.try_into() {
+        Ok(array) => array,
+        Err(_) => unreachable!("Collected {} elements", N),
+    }
+}
+
+/// Arrays are inline, as in C.
+impl<T: IntoRawConversion, const N: usize> IntoRawConversion for [T; N] {
+    type Raw = [T::Raw; N];
+    type Ptr = *mut Self::Raw;
 
     fn into_raw(self) -> Result<Self::Raw, Error> {
-        Ok(std::ffi::CString::new(self)?.into_raw())
+        Ok(vec_into_array(
+            IntoIterator::into_iter(self)
+                .map(T::into_raw)
+                .collect::<Result<Vec<_>, Error>>()?,
+        ))
     }
 
     fn into_ptr(self) -> Result<Self::Ptr, Error> {
-        self.into_raw()
+        box_into_ptr(self)
     }
 }
 
-impl FromRawConversion for String {
-    type Raw = *mut ::libc::c_char;
-    type Ptr = Self::Raw;
+impl<T: FromRawConversion, const N: usize> FromRawConversion for [T; N] {
+    type Raw = [T::Raw; N];
+    type Ptr = *mut Self::Raw;
 
     unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
-        Ok(std::ffi::CString::from_raw(raw).into_string()?)
+        Ok(vec_into_array(
+            IntoIterator::into_iter(raw)
+                .map(|value| T::from_raw(value))
+                .collect::<Result<Vec<_>, Error>>()?,
+        ))
     }
 
     unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
-        Self::from_raw(ptr)
+        box_from_ptr(ptr)
     }
 }
 
-impl PtrAsReference for String {
-    type Raw = *mut ::libc::c_char;
-    type Ptr = Self::Raw;
+impl<T: PtrAsReference, const N: usize> PtrAsReference for [T; N] {
+    type Raw = [T::Raw; N];
+    type Ptr = *const Self::Raw;
 
     unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-        Ok(::std::ffi::CStr::from_ptr(*raw).to_str()?.to_owned())
+        Ok(vec_into_array(
+            raw.iter()
+                .map(|value| T::raw_as_ref(value))
+                .collect::<Result<Vec<_>, Error>>()?,
+        ))
     }
 
     unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
-        Self::raw_as_ref(&ptr)
+        Self::raw_as_ref(&*ptr)
     }
 }
 
-impl<'a> PtrAsReference for &'a str {
-    type Raw = *mut ::libc::c_char;
-    type Ptr = Self::Raw;
+macro_rules! set_marshalled_type {
+    ($($set:ident { $($bound:path),* })*) => {
+        $(
+            impl<T: IntoRawConversion> IntoRawConversion for std::collections::$set<T> {
+                type Raw = CMutVec<T::Raw>;
+                type Ptr = *mut Self::Raw;
 
-    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-        Ok(std::ffi::CStr::from_ptr(*raw).to_str()?)
-    }
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    self.into_iter().collect::<Vec<_>>().into_raw()
+                }
 
-    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
-        Self::raw_as_ref(&ptr)
-    }
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    box_into_ptr(self)
+                }
+            }
+
+            impl<T: FromRawConversion $(+ $bound)*> FromRawConversion for std::collections::$set<T> {
+                type Raw = CMutVec<T::Raw>;
+                type Ptr = *mut Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    Ok(Vec::from_raw(raw)?.into_iter().collect())
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    box_from_ptr(ptr)
+                }
+            }
+
+            impl<T: PtrAsReference $(+ $bound)*> PtrAsReference for std::collections::$set<T> {
+                type Raw = CVec<T::Raw>;
+                type Ptr = *const Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    Ok(Vec::raw_as_ref(raw)?.into_iter().collect())
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::raw_as_ref(&*ptr)
+                }
+            }
+        )*
+    };
 }
 
+set_marshalled_type!(
+    HashSet { std::hash::Hash, Eq }
+    BTreeSet { Ord }
+);
+
 #[repr(C)]
-pub struct COption<T> {
-    pub ptr: *const T,
+pub struct CMap<K, V> {
+    pub keys: *const K,
+    pub values: *const V,
+    pub len: usize,
 }
 
 #[repr(C)]
-pub struct CMutOption<T> {
-    pub ptr: *mut T,
+pub struct CMutMap<K, V> {
+    pub keys: *mut K,
+    pub values: *mut V,
+    pub len: usize,
 }
 
-impl<T: IntoRawConversion> IntoRawConversion for Option<T> {
-    type Raw = CMutOption<T::Raw>;
+fn boxed_slice_into_ptr<T>(vec: Vec<T>) -> *mut T {
+    Box::into_raw(vec.into_boxed_slice()).cast()
+}
+
+unsafe fn boxed_slice_from_ptr<T>(ptr: *mut T, len: usize) -> Vec<T> {
+    Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)).into_vec()
+}
+
+macro_rules! map_marshalled_type {
+    ($($map:ident { $($bound:path),* })*) => {
+        $(
+            impl<K: IntoRawConversion, V: IntoRawConversion> IntoRawConversion
+                for std::collections::$map<K, V>
+            {
+                type Raw = CMutMap<K::Raw, V::Raw>;
+                type Ptr = *mut Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    let mut keys = Vec::with_capacity(self.len());
+                    let mut values = Vec::with_capacity(self.len());
+                    for (key, value) in self {
+                        keys.push(key.into_raw()?);
+                        values.push(value.into_raw()?);
+                    }
+                    Ok(CMutMap {
+                        len: keys.len(),
+                        keys: boxed_slice_into_ptr(keys),
+                        values: boxed_slice_into_ptr(values),
+                    })
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    box_into_ptr(self)
+                }
+            }
+
+            impl<K: FromRawConversion $(+ $bound)*, V: FromRawConversion> FromRawConversion
+                for std::collections::$map<K, V>
+            {
+                type Raw = CMutMap<K::Raw, V::Raw>;
+                type Ptr = *mut Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    boxed_slice_from_ptr(raw.keys, raw.len)
+                        .into_iter()
+                        .zip(boxed_slice_from_ptr(raw.values, raw.len))
+                        .map(|(key, value)| Ok((K::from_raw(key)?, V::from_raw(value)?)))
+                        .collect()
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    box_from_ptr(ptr)
+                }
+            }
+
+            impl<K: PtrAsReference $(+ $bound)*, V: PtrAsReference> PtrAsReference
+                for std::collections::$map<K, V>
+            {
+                type Raw = CMap<K::Raw, V::Raw>;
+                type Ptr = *const Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    std::slice::from_raw_parts(raw.keys, raw.len)
+                        .iter()
+                        .zip(std::slice::from_raw_parts(raw.values, raw.len))
+                        .map(|(key, value)| Ok((K::raw_as_ref(key)?, V::raw_as_ref(value)?)))
+                        .collect()
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::raw_as_ref(&*ptr)
+                }
+            }
+        )*
+    };
+}
+
+map_marshalled_type!(
+    HashMap { std::hash::Hash, Eq }
+    BTreeMap { Ord }
+);
+
+/// Strings are passed with their length, so they may contain zero-bytes.
+#[repr(C)]
+pub struct CBytes {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct CMutBytes {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+impl IntoRawConversion for String {
+    type Raw = CMutBytes;
     type Ptr = *mut Self::Raw;
 
     fn into_raw(self) -> Result<Self::Raw, Error> {
-        Ok(CMutOption {
-            ptr: if let Some(value) = self {
-                box_into_ptr(value)?
-            } else {
-                ::std::ptr::null_mut()
-            },
+        let bytes = self.into_bytes();
+        Ok(CMutBytes {
+            len: bytes.len(),
+            ptr: boxed_slice_into_ptr(bytes),
         })
     }
 
@@ -220,16 +493,12 @@ impl<T: IntoRawConversion> IntoRawConversion for Option<T> {
     }
 }
 
-impl<T: FromRawConversion> FromRawConversion for Option<T> {
-    type Raw = CMutOption<T::Raw>;
+impl FromRawConversion for String {
+    type Raw = CMutBytes;
     type Ptr = *mut Self::Raw;
 
     unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
-        Ok(if !raw.ptr.is_null() {
-            Some(box_from_ptr(raw.ptr)?)
-        } else {
-            None
-        })
+        Ok(String::from_utf8(boxed_slice_from_ptr(raw.ptr, raw.len))?)
     }
 
     unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
@@ -237,16 +506,12 @@ impl<T: FromRawConversion> FromRawConversion for Option<T> {
     }
 }
 
-impl<T: PtrAsReference> PtrAsReference for Option<T> {
-    type Raw = COption<T::Raw>;
+impl PtrAsReference for String {
+    type Raw = CBytes;
     type Ptr = *const Self::Raw;
 
     unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-        if let Some(value) = raw.ptr.as_ref() {
-            Ok(Some(T::raw_as_ref(value)?))
-        } else {
-            Ok(None)
-        }
+        Ok(<&str>::raw_as_ref(raw)?.to_owned())
     }
 
     unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
@@ -254,32 +519,60 @@ impl<T: PtrAsReference> PtrAsReference for Option<T> {
     }
 }
 
-#[repr(C)]
-pub struct CResult<T, E> {
-    pub ok: *const T,
-    pub err: *const E,
+impl PtrAsReference for &str {
+    type Raw = CBytes;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(std::str::from_utf8(std::slice::from_raw_parts(
+            raw.ptr, raw.len,
+        ))?)
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
 }
 
-#[repr(C)]
-pub struct CMutResult<T, E> {
-    pub ok: *mut T,
-    pub err: *mut E,
+/// A byte buffer, marshalled like a `String` but without the UTF-8 requirement.
+/// Unlike `Vec<u8>`, which is an array of numbers, it is a string in the target language.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for Bytes {
+    fn from(vec: Vec<u8>) -> Self {
+        Bytes(vec)
+    }
 }
 
-impl<T: IntoRawConversion, E: IntoRawConversion> IntoRawConversion for Result<T, E> {
-    type Raw = CMutResult<T::Raw, E::Raw>;
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl IntoRawConversion for Bytes {
+    type Raw = CMutBytes;
     type Ptr = *mut Self::Raw;
 
     fn into_raw(self) -> Result<Self::Raw, Error> {
-        Ok(match self {
-            Ok(value) => CMutResult {
-                ok: box_into_ptr(value)?,
-                err: std::ptr::null_mut(),
-            },
-            Err(value) => CMutResult {
-                ok: std::ptr::null_mut(),
-                err: box_into_ptr(value)?,
-            },
+        Ok(CMutBytes {
+            len: self.0.len(),
+            ptr: boxed_slice_into_ptr(self.0),
         })
     }
 
@@ -288,16 +581,12 @@ impl<T: IntoRawConversion, E: IntoRawConversion> IntoRawConversion for Result<T,
     }
 }
 
-impl<T: FromRawConversion, E: FromRawConversion> FromRawConversion for Result<T, E> {
-    type Raw = CMutResult<T::Raw, E::Raw>;
+impl FromRawConversion for Bytes {
+    type Raw = CMutBytes;
     type Ptr = *mut Self::Raw;
 
     unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
-        Ok(if !raw.ok.is_null() {
-            Ok(box_from_ptr(raw.ok)?)
-        } else {
-            Err(box_from_ptr(raw.err)?)
-        })
+        Ok(Bytes(boxed_slice_from_ptr(raw.ptr, raw.len)))
     }
 
     unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
@@ -305,18 +594,12 @@ impl<T: FromRawConversion, E: FromRawConversion> FromRawConversion for Result<T,
     }
 }
 
-impl<T: PtrAsReference, E: PtrAsReference> PtrAsReference for Result<T, E> {
-    type Raw = CResult<T::Raw, E::Raw>;
+impl PtrAsReference for Bytes {
+    type Raw = CBytes;
     type Ptr = *const Self::Raw;
 
     unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-        if let Some(value) = raw.ok.as_ref() {
-            Ok(Ok(T::raw_as_ref(value)?))
-        } else if let Some(value) = raw.err.as_ref() {
-            Ok(Err(E::raw_as_ref(value)?))
-        } else {
-            unreachable!()
-        }
+        Ok(Bytes(std::slice::from_raw_parts(raw.ptr, raw.len).to_vec()))
     }
 
     unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
@@ -324,60 +607,1106 @@ impl<T: PtrAsReference, E: PtrAsReference> PtrAsReference for Result<T, E> {
     }
 }
 
-#[repr(C)]
-pub struct CSlice<T> {
-    pub ptr: *const T,
-    pub len: usize,
-}
-
-macro_rules! primitive_marshalled_type {
-    ($($typ:ty )*) => {
+// Other string types are marshalled exactly like `String`.
+macro_rules! string_like_marshalled_type {
+    ($($typ:ty => $into_string:expr),*) => {
         $(
             impl IntoRawConversion for $typ {
-                type Raw = Self;
-                type Ptr = Self::Raw;
+                type Raw = <String as IntoRawConversion>::Raw;
+                type Ptr = <String as IntoRawConversion>::Ptr;
 
                 fn into_raw(self) -> Result<Self::Raw, Error> {
-                    Ok(self)
+                    ($into_string)(self).into_raw()
                 }
 
                 fn into_ptr(self) -> Result<Self::Ptr, Error> {
-                    Ok(self)
+                    ($into_string)(self).into_ptr()
                 }
             }
 
             impl FromRawConversion for $typ {
-                type Raw = Self;
-                type Ptr = Self::Raw;
+                type Raw = <String as FromRawConversion>::Raw;
+                type Ptr = <String as FromRawConversion>::Ptr;
 
                 unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
-                    Ok(raw)
+                    Ok(String::from_raw(raw)?.into())
                 }
 
                 unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
-                    Ok(ptr)
+                    Ok(String::from_ptr(ptr)?.into())
                 }
             }
 
             impl PtrAsReference for $typ {
-                type Raw = Self;
-                type Ptr = Self::Raw;
+                type Raw = <String as PtrAsReference>::Raw;
+                type Ptr = <String as PtrAsReference>::Ptr;
 
                 unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-                    Ok(raw.clone())
+                    Ok(<&str>::raw_as_ref(raw)?.into())
                 }
 
                 unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
-                    Ok(ptr)
+                    Ok(<&str>::ptr_as_ref(ptr)?.into())
                 }
             }
 
-            impl<'a> PtrAsReference for &'a [$typ] {
-                type Raw = CSlice<$typ>;
-                type Ptr = *const Self::Raw;
+            impl SliceAsReference for $typ {
+                type Raw = <Self as PtrAsReference>::Raw;
+
+                unsafe fn ptr_as_slice<'a>(
+                    ptr: *const CSlice<Self::Raw>,
+                ) -> Result<SliceRef<'a, Self>, Error> {
+                    owned_slice_as_ref(ptr)
+                }
+            }
+        )*
+    };
+}
+
+string_like_marshalled_type!(
+    Box<str> => str::into_string,
+    std::borrow::Cow<'static, str> => std::borrow::Cow::into_owned,
+    std::rc::Rc<str> => |string: std::rc::Rc<str>| string.to_string(),
+    std::sync::Arc<str> => |string: std::sync::Arc<str>| string.to_string()
+);
+
+// Paths and OS strings are passed as their bytes, which are not necessarily UTF-8.
+#[cfg(unix)]
+unsafe fn os_str_from_bytes<'a>(raw: &CBytes) -> Result<&'a std::ffi::OsStr, Error> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::ffi::OsStr::from_bytes(std::slice::from_raw_parts(
+        raw.ptr, raw.len,
+    )))
+}
+
+#[cfg(not(unix))]
+unsafe fn os_str_from_bytes<'a>(raw: &CBytes) -> Result<&'a std::ffi::OsStr, Error> {
+    Ok(<&str>::raw_as_ref(raw)?.as_ref())
+}
+
+impl IntoRawConversion for std::ffi::OsString {
+    type Raw = CMutBytes;
+    type Ptr = *mut Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        let bytes = self.into_encoded_bytes();
+        Ok(CMutBytes {
+            len: bytes.len(),
+            ptr: boxed_slice_into_ptr(bytes),
+        })
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        box_into_ptr(self)
+    }
+}
+
+impl FromRawConversion for std::ffi::OsString {
+    type Raw = CMutBytes;
+    type Ptr = *mut Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        // `raw` was created by `into_raw` and therefore holds valid encoded bytes.
+        Ok(std::ffi::OsString::from_encoded_bytes_unchecked(
+            boxed_slice_from_ptr(raw.ptr, raw.len),
+        ))
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        box_from_ptr(ptr)
+    }
+}
+
+impl PtrAsReference for std::ffi::OsString {
+    type Raw = CBytes;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(os_str_from_bytes(raw)?.to_owned())
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+impl PtrAsReference for &std::ffi::OsStr {
+    type Raw = CBytes;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        os_str_from_bytes(raw)
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+impl IntoRawConversion for std::path::PathBuf {
+    type Raw = CMutBytes;
+    type Ptr = *mut Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        self.into_os_string().into_raw()
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        box_into_ptr(self)
+    }
+}
+
+impl FromRawConversion for std::path::PathBuf {
+    type Raw = CMutBytes;
+    type Ptr = *mut Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(std::ffi::OsString::from_raw(raw)?.into())
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        box_from_ptr(ptr)
+    }
+}
+
+impl PtrAsReference for std::path::PathBuf {
+    type Raw = CBytes;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(os_str_from_bytes(raw)?.into())
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+impl PtrAsReference for &std::path::Path {
+    type Raw = CBytes;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(std::path::Path::new(os_str_from_bytes(raw)?))
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+/// Zero-terminated C strings, as `String` was passed before it carried its length.
+impl IntoRawConversion for std::ffi::CString {
+    type Raw = *mut ::libc::c_char;
+    type Ptr = Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(std::ffi::CString::into_raw(self))
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        IntoRawConversion::into_raw(self)
+    }
+}
+
+impl FromRawConversion for std::ffi::CString {
+    type Raw = *mut ::libc::c_char;
+    type Ptr = Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(std::ffi::CString::from_raw(raw))
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        FromRawConversion::from_raw(ptr)
+    }
+}
+
+impl PtrAsReference for std::ffi::CString {
+    type Raw = *mut ::libc::c_char;
+    type Ptr = Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(::std::ffi::CStr::from_ptr(*raw).to_owned())
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&ptr)
+    }
+}
+
+impl SliceAsReference for String {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for Bytes {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for std::ffi::OsString {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for std::path::PathBuf {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for &str {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for &std::ffi::OsStr {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+impl SliceAsReference for &std::path::Path {
+    type Raw = <Self as PtrAsReference>::Raw;
+
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error> {
+        owned_slice_as_ref(ptr)
+    }
+}
+
+#[repr(C)]
+pub struct COption<T> {
+    pub ptr: *const T,
+}
+
+#[repr(C)]
+pub struct CMutOption<T> {
+    pub ptr: *mut T,
+}
+
+impl<T: IntoRawConversion> IntoRawConversion for Option<T> {
+    type Raw = CMutOption<T::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(CMutOption {
+            ptr: if let Some(value) = self {
+                box_into_ptr(value)?
+            } else {
+                ::std::ptr::null_mut()
+            },
+        })
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        box_into_ptr(self)
+    }
+}
+
+impl<T: FromRawConversion> FromRawConversion for Option<T> {
+    type Raw = CMutOption<T::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(if !raw.ptr.is_null() {
+            Some(box_from_ptr(raw.ptr)?)
+        } else {
+            None
+        })
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        box_from_ptr(ptr)
+    }
+}
+
+impl<T: PtrAsReference> PtrAsReference for Option<T> {
+    type Raw = COption<T::Raw>;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        if let Some(value) = raw.ptr.as_ref() {
+            Ok(Some(T::raw_as_ref(value)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+#[repr(C)]
+pub struct CBox<T> {
+    pub ptr: *const T,
+}
+
+#[repr(C)]
+pub struct CMutBox<T> {
+    pub ptr: *mut T,
+}
+
+impl<T: IntoRawConversion> IntoRawConversion for Box<T> {
+    type Raw = CMutBox<T::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(CMutBox {
+            ptr: box_into_ptr(*self)?,
+        })
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        box_into_ptr(self)
+    }
+}
+
+impl<T: FromRawConversion> FromRawConversion for Box<T> {
+    type Raw = CMutBox<T::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(Box::new(box_from_ptr(raw.ptr)?))
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        box_from_ptr(ptr)
+    }
+}
+
+impl<T: PtrAsReference> PtrAsReference for Box<T> {
+    type Raw = CBox<T::Raw>;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(Box::new(T::raw_as_ref(&*raw.ptr)?))
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+#[repr(C)]
+pub struct CResult<T, E> {
+    pub ok: *const T,
+    pub err: *const E,
+}
+
+#[repr(C)]
+pub struct CMutResult<T, E> {
+    pub ok: *mut T,
+    pub err: *mut E,
+}
+
+impl<T: IntoRawConversion, E: IntoRawConversion> IntoRawConversion for Result<T, E> {
+    type Raw = CMutResult<T::Raw, E::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(match self {
+            Ok(value) => CMutResult {
+                ok: box_into_ptr(value)?,
+                err: std::ptr::null_mut(),
+            },
+            Err(value) => CMutResult {
+                ok: std::ptr::null_mut(),
+                err: box_into_ptr(value)?,
+            },
+        })
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        box_into_ptr(self)
+    }
+}
+
+impl<T: FromRawConversion, E: FromRawConversion> FromRawConversion for Result<T, E> {
+    type Raw = CMutResult<T::Raw, E::Raw>;
+    type Ptr = *mut Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(if !raw.ok.is_null() {
+            Ok(box_from_ptr(raw.ok)?)
+        } else {
+            Err(box_from_ptr(raw.err)?)
+        })
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        box_from_ptr(ptr)
+    }
+}
+
+impl<T: PtrAsReference, E: PtrAsReference> PtrAsReference for Result<T, E> {
+    type Raw = CResult<T::Raw, E::Raw>;
+    type Ptr = *const Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        if let Some(value) = raw.ok.as_ref() {
+            Ok(Ok(T::raw_as_ref(value)?))
+        } else if let Some(value) = raw.err.as_ref() {
+            Ok(Err(E::raw_as_ref(value)?))
+        } else {
+            unreachable!()
+        }
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::raw_as_ref(&*ptr)
+    }
+}
+
+#[repr(C)]
+pub struct CHandle<T> {
+    pub ptr: *mut T,
+    pub drop: unsafe extern "C" fn(*mut CHandle<T>),
+    /// The number of functions borrowing the handle, or -1 while it is mutably borrowed.
+    pub borrows: isize,
+}
+
+unsafe extern "C" fn drop_handle<T>(handle: *mut CHandle<T>) {
+    let handle = Box::from_raw(handle);
+    drop(Box::from_raw(handle.ptr));
+}
+
+/// An object living in Rust but owned by the caller, such as a database connection.
+/// It is passed as a pointer which carries its own destructor, so that the caller
+/// drops the object when it is done with it. Functions borrow it back as `&Handle<T>`
+/// or `&mut Handle<T>`.
+pub struct Handle<T> {
+    ptr: std::ptr::NonNull<T>,
+    /// Handles which have been released to the caller, or borrowed back from it,
+    /// do not drop the object.
+    owned: bool,
+}
+
+impl<T> Handle<T> {
+    pub fn new(value: T) -> Self {
+        Handle {
+            ptr: std::ptr::NonNull::from(Box::leak(Box::new(value))),
+            owned: true,
+        }
+    }
+
+    unsafe fn borrowed(handle: *mut CHandle<T>) -> Result<Self, Error> {
+        handle
+            .as_ref()
+            .and_then(|handle| std::ptr::NonNull::new(handle.ptr))
+            .map(|ptr| Handle { ptr, owned: false })
+            .ok_or_else(|| Error::NullHandle(std::any::type_name::<T>()))
+    }
+}
+
+impl<T> From<T> for Handle<T> {
+    fn from(value: T) -> Self {
+        Handle::new(value)
+    }
+}
+
+impl<T> std::ops::Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> std::ops::DerefMut for Handle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for Handle<T> {
+    fn drop(&mut self) {
+        if self.owned {
+            drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+        }
+    }
+}
+
+/// Only owned handles can be returned. A borrowed handle, e.g. one swapped out of a
+/// `&mut Handle<T>` argument, is already owned by the caller.
+impl<T> IntoRawConversion for Handle<T> {
+    type Raw = *mut CHandle<T>;
+    type Ptr = Self::Raw;
+
+    fn into_raw(mut self) -> Result<Self::Raw, Error> {
+        if !self.owned {
+            return Err(Error::ReturnedBorrowedHandle(std::any::type_name::<T>()));
+        }
+        self.owned = false;
+        Ok(Box::into_raw(Box::new(CHandle {
+            ptr: self.ptr.as_ptr(),
+            drop: drop_handle::<T>,
+            borrows: 0,
+        })))
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        self.into_raw()
+    }
+}
+
+/// The caller takes ownership of handles as soon as it receives them, so freeing a
+/// returned handle leaves the object alive for the caller to drop.
+impl<T> FromRawConversion for Handle<T> {
+    type Raw = *mut CHandle<T>;
+    type Ptr = Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Handle::borrowed(raw)
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        Self::from_raw(ptr)
+    }
+}
+
+/// Receivers of methods, `&self` and `&mut self`, which Lua passes as a pointer. Types with
+/// value marshalling are copied like any other `&T` argument. Any other type is borrowed
+/// from a `Handle<T>`. Generated code picks between the two by calling `receive` on a
+/// `&Receiver<T>`, which resolves to `ValueReceiver` if `T: PtrAsReference` and to
+/// `HandleReceiver` otherwise.
+pub struct Receiver<T>(std::marker::PhantomData<T>);
+
+impl<T> Receiver<T> {
+    pub fn new() -> Self {
+        Receiver(std::marker::PhantomData)
+    }
+}
+
+impl<T> Default for Receiver<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait ValueReceiver<T> {
+    /// # Safety
+    ///
+    /// `ptr` must point to the `PtrAsReference::Raw` of `T` for the duration of the call.
+    unsafe fn receive(&self, ptr: *const std::ffi::c_void) -> Result<Owned<T>, Error>;
+
+    /// Values cannot be mutably borrowed, which fails to compile.
+    ///
+    /// # Safety
+    ///
+    /// Never called.
+    unsafe fn receive_mut(&self, ptr: *const std::ffi::c_void) -> Result<Owned<T>, Error>
+    where
+        T: MutArgument;
+}
+
+impl<T: PtrAsReference> ValueReceiver<T> for Receiver<T> {
+    unsafe fn receive(&self, ptr: *const std::ffi::c_void) -> Result<Owned<T>, Error> {
+        T::raw_as_ref(&*(ptr as *const T::Raw)).map(Owned)
+    }
+
+    unsafe fn receive_mut(&self, _: *const std::ffi::c_void) -> Result<Owned<T>, Error>
+    where
+        T: MutArgument,
+    {
+        unreachable!()
+    }
+}
+
+pub trait HandleReceiver<T> {
+    /// # Safety
+    ///
+    /// `ptr` must point to a `CHandle<T>` for the duration of the call.
+    unsafe fn receive(&self, ptr: *const std::ffi::c_void) -> Result<HandleBorrow<T>, Error>;
+
+    /// # Safety
+    ///
+    /// `ptr` must point to a `CHandle<T>` for the duration of the call.
+    unsafe fn receive_mut(&self, ptr: *const std::ffi::c_void) -> Result<HandleBorrow<T>, Error>;
+}
+
+impl<T> HandleReceiver<T> for &Receiver<T> {
+    unsafe fn receive(&self, ptr: *const std::ffi::c_void) -> Result<HandleBorrow<T>, Error> {
+        HandleBorrow::new(ptr as *mut CHandle<T>, false)
+    }
+
+    unsafe fn receive_mut(&self, ptr: *const std::ffi::c_void) -> Result<HandleBorrow<T>, Error> {
+        HandleBorrow::new(ptr as *mut CHandle<T>, true)
+    }
+}
+
+/// A handle borrowed by a function, like a `RefCell` borrow. Borrowing a handle which is
+/// mutably borrowed, or mutably borrowing a borrowed one, fails with `BorrowedHandle`, e.g.
+/// when the same handle is passed twice to a function taking `&mut Handle<T>`.
+pub struct HandleBorrow<T> {
+    handle: Handle<T>,
+    raw: *mut CHandle<T>,
+}
+
+impl<T> HandleBorrow<T> {
+    unsafe fn new(raw: *mut CHandle<T>, mutable: bool) -> Result<Self, Error> {
+        let handle = Handle::borrowed(raw)?;
+        let borrows = &mut (*raw).borrows;
+        match (*borrows, mutable) {
+            (0, true) => *borrows = -1,
+            (count, false) if count >= 0 => *borrows = count + 1,
+            _ => return Err(Error::BorrowedHandle(std::any::type_name::<T>())),
+        }
+        Ok(HandleBorrow { handle, raw })
+    }
+}
+
+impl<T> std::ops::Deref for HandleBorrow<T> {
+    type Target = Handle<T>;
+
+    fn deref(&self) -> &Handle<T> {
+        &self.handle
+    }
+}
+
+impl<T> std::ops::DerefMut for HandleBorrow<T> {
+    fn deref_mut(&mut self) -> &mut Handle<T> {
+        &mut self.handle
+    }
+}
+
+impl<T> Drop for HandleBorrow<T> {
+    fn drop(&mut self) {
+        let borrows = unsafe { &mut (*self.raw).borrows };
+        *borrows = if *borrows < 0 { 0 } else { *borrows - 1 };
+    }
+}
+
+impl<T> RefArgument for Handle<T> {
+    type Ptr = *mut CHandle<T>;
+    type Ref = HandleBorrow<T>;
+
+    unsafe fn ptr_as_deref(ptr: Self::Ptr) -> Result<Self::Ref, Error> {
+        HandleBorrow::new(ptr, false)
+    }
+}
+
+impl<T> MutArgument for Handle<T> {
+    type Mut = HandleBorrow<T>;
+
+    unsafe fn ptr_as_deref_mut(ptr: Self::Ptr) -> Result<Self::Mut, Error> {
+        HandleBorrow::new(ptr, true)
+    }
+}
+
+/// Callback arguments, such as `&dyn Fn(A) -> R`, which are declared as `fn(A) -> R`.
+/// The callback returns a non-zero status if it fails, with the error message in its last
+/// argument.
+pub trait Callback {
+    type Ptr: Copy;
+    type Args;
+    type Output;
+
+    /// The arguments are converted for the duration of the call.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid callback for the duration of the call.
+    unsafe fn call(ptr: Self::Ptr, args: Self::Args) -> Result<Self::Output, Error>;
+}
+
+macro_rules! callback_marshalled_type {
+    ($(($($arg:ident $typ:ident)*))*) => {
+        $(
+            impl<$($typ,)* R> Callback for fn($($typ),*) -> R
+            where
+                $($typ: IntoRawConversion
+                    + FromRawConversion<Ptr = <$typ as IntoRawConversion>::Ptr>,
+                <$typ as IntoRawConversion>::Ptr: Copy,)*
+                R: PtrAsReference,
+            {
+                type Ptr = unsafe extern "C" fn(
+                    $(<$typ as IntoRawConversion>::Ptr,)*
+                    *mut R::Ptr,
+                    *mut <String as PtrAsReference>::Ptr,
+                ) -> u32;
+                type Args = ($($typ,)*);
+                type Output = R;
+
+                unsafe fn call(ptr: Self::Ptr, ($($arg,)*): Self::Args) -> Result<R, Error> {
+                    $(let $arg = $arg.into_ptr()?;)*
+                    let mut ret = std::mem::MaybeUninit::uninit();
+                    let mut err = std::mem::MaybeUninit::uninit();
+                    let status = ptr($($arg,)* ret.as_mut_ptr(), err.as_mut_ptr());
+                    $(<$typ as FromRawConversion>::from_ptr($arg)?;)*
+                    if status == 0 {
+                        R::ptr_as_ref(ret.assume_init())
+                    } else {
+                        Err(Error::Callback(String::ptr_as_ref(err.assume_init())?))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+callback_marshalled_type!(
+    ()
+    (a0 A0)
+    (a0 A0 a1 A1)
+    (a0 A0 a1 A1 a2 A2)
+    (a0 A0 a1 A1 a2 A2 a3 A3)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6 a7 A7)
+);
+
+/// Signatures of closures returned to the caller, declared as `fn(A) -> R`.
+pub trait Signature {
+    type Closure: ?Sized;
+    type Call: Copy;
+    type Free: Copy;
+}
+
+/// A closure returned to the caller, such as a `Box<dyn Fn(&str) -> bool>`, which the
+/// caller can call and drops when it is done with it.
+pub struct Closure<F: Signature>(Option<Box<F::Closure>>);
+
+#[repr(C)]
+pub struct CClosure<F: Signature> {
+    pub call: F::Call,
+    /// Frees the values returned by `call`.
+    pub free: F::Free,
+    pub drop: unsafe extern "C" fn(*mut CClosure<F>),
+    closure: Box<F::Closure>,
+}
+
+unsafe extern "C" fn drop_closure<F: Signature>(closure: *mut CClosure<F>) {
+    drop(Box::from_raw(closure));
+}
+
+macro_rules! closure_marshalled_type {
+    ($(($($arg:ident $typ:ident)*))*) => {
+        $(
+            impl<$($typ,)* R> Signature for fn($($typ),*) -> R
+            where
+                $($typ: PtrAsReference + 'static,)*
+                R: IntoRawConversion + 'static,
+            {
+                type Closure = dyn Fn($($typ),*) -> R;
+                type Call = unsafe extern "C" fn(
+                    *const CClosure<Self>,
+                    $(<$typ as PtrAsReference>::Ptr,)*
+                    *mut <R as IntoRawConversion>::Ptr,
+                ) -> u32;
+                type Free = unsafe extern "C" fn(<R as IntoRawConversion>::Ptr) -> u32;
+            }
+
+            impl<$($typ,)* R> Closure<fn($($typ),*) -> R>
+            where
+                $($typ: PtrAsReference + 'static,)*
+                R: IntoRawConversion
+                    + FromRawConversion<Ptr = <R as IntoRawConversion>::Ptr>
+                    + 'static,
+            {
+                pub fn new(closure: impl Fn($($typ),*) -> R + 'static) -> Self {
+                    let closure: Box<dyn Fn($($typ),*) -> R> = Box::new(closure);
+                    Closure(Some(closure))
+                }
+
+                unsafe extern "C" fn call(
+                    closure: *const CClosure<fn($($typ),*) -> R>,
+                    $($arg: <$typ as PtrAsReference>::Ptr,)*
+                    output: *mut <R as IntoRawConversion>::Ptr,
+                ) -> u32 {
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+                        || -> Result<u32, Error> {
+                            *output = ((*closure).closure)($($typ::ptr_as_ref($arg)?),*)
+                                .into_ptr()?;
+                            Ok(0)
+                        },
+                    ))
+                    .unwrap_or(Ok(2))
+                    .unwrap_or(1)
+                }
+
+                unsafe extern "C" fn free(output: <R as IntoRawConversion>::Ptr) -> u32 {
+                    <R as FromRawConversion>::from_ptr(output).is_err() as u32
+                }
+            }
+
+            impl<$($typ,)* R> IntoRawConversion for Closure<fn($($typ),*) -> R>
+            where
+                $($typ: PtrAsReference + 'static,)*
+                R: IntoRawConversion
+                    + FromRawConversion<Ptr = <R as IntoRawConversion>::Ptr>
+                    + 'static,
+            {
+                type Raw = *mut CClosure<fn($($typ),*) -> R>;
+                type Ptr = Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    Ok(Box::into_raw(Box::new(CClosure {
+                        call: Self::call,
+                        free: Self::free,
+                        drop: drop_closure::<fn($($typ),*) -> R>,
+                        closure: self.0.expect("Closure has been released"),
+                    })))
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    self.into_raw()
+                }
+            }
+
+            /// The caller takes ownership of closures as soon as it receives them, so freeing
+            /// a returned closure leaves it alive for the caller to drop.
+            impl<$($typ,)* R> FromRawConversion for Closure<fn($($typ),*) -> R>
+            where
+                $($typ: PtrAsReference + 'static,)*
+                R: IntoRawConversion + 'static,
+            {
+                type Raw = *mut CClosure<fn($($typ),*) -> R>;
+                type Ptr = Self::Raw;
+
+                unsafe fn from_raw(_: Self::Raw) -> Result<Self, Error> {
+                    Ok(Closure(None))
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::from_raw(ptr)
+                }
+            }
+        )*
+    };
+}
+
+closure_marshalled_type!(
+    ()
+    (a0 A0)
+    (a0 A0 a1 A1)
+    (a0 A0 a1 A1 a2 A2)
+    (a0 A0 a1 A1 a2 A2 a3 A3)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6 a7 A7)
+);
+
+/// The next item of an iterator returned to the caller, `None` once it is exhausted. It is
+/// marshalled like an `Option`, but an item which is itself `nil` in the target language,
+/// such as `Some(None)`, is marshalled as `null` so that it does not end the loop.
+pub struct IteratorItem<T>(pub Option<T>);
+
+impl<T: IntoRawConversion> IntoRawConversion for IteratorItem<T> {
+    type Raw = <Option<T> as IntoRawConversion>::Raw;
+    type Ptr = <Option<T> as IntoRawConversion>::Ptr;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        self.0.into_raw()
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        self.0.into_ptr()
+    }
+}
+
+impl<T: FromRawConversion> FromRawConversion for IteratorItem<T> {
+    type Raw = <Option<T> as FromRawConversion>::Raw;
+    type Ptr = <Option<T> as FromRawConversion>::Ptr;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Option::from_raw(raw).map(IteratorItem)
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        Option::from_ptr(ptr).map(IteratorItem)
+    }
+}
+
+macro_rules! tuple_marshalled_type {
+    ($($name:ident { $($index:tt $field:ident $typ:ident)* })*) => {
+        $(
+            #[repr(C)]
+            pub struct $name<$($typ),*> {
+                $(pub $field: $typ),*
+            }
+
+            impl<$($typ: IntoRawConversion),*> IntoRawConversion for ($($typ,)*) {
+                type Raw = $name<$($typ::Raw),*>;
+                type Ptr = *mut Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    Ok($name {
+                        $($field: self.$index.into_raw()?),*
+                    })
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    box_into_ptr(self)
+                }
+            }
+
+            impl<$($typ: FromRawConversion),*> FromRawConversion for ($($typ,)*) {
+                type Raw = $name<$($typ::Raw),*>;
+                type Ptr = *mut Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    Ok(($($typ::from_raw(raw.$field)?,)*))
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    box_from_ptr(ptr)
+                }
+            }
+
+            impl<$($typ: PtrAsReference),*> PtrAsReference for ($($typ,)*) {
+                type Raw = $name<$($typ::Raw),*>;
+                type Ptr = *const Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    Ok(($($typ::raw_as_ref(&raw.$field)?,)*))
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::raw_as_ref(&*ptr)
+                }
+            }
+        )*
+    };
+}
+
+tuple_marshalled_type!(
+    CTuple2 { 0 _0 T0 1 _1 T1 }
+    CTuple3 { 0 _0 T0 1 _1 T1 2 _2 T2 }
+    CTuple4 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 }
+    CTuple5 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 }
+    CTuple6 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 }
+    CTuple7 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 6 _6 T6 }
+    CTuple8 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 6 _6 T6 7 _7 T7 }
+);
+
+#[repr(C)]
+pub struct CSlice<T> {
+    pub ptr: *const T,
+    pub len: usize,
+}
+
+/// A slice argument, `&[T]`. Slices of primitive types borrow the raw data, while the
+/// elements of other slices are converted into an owned `Vec`.
+pub enum SliceRef<'a, T> {
+    Borrowed(&'a [T]),
+    Owned(Vec<T>),
+}
+
+impl<T> std::ops::Deref for SliceRef<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match *self {
+            SliceRef::Borrowed(slice) => slice,
+            SliceRef::Owned(ref vec) => vec,
+        }
+    }
+}
+
+// Types with #[derive(CMarshalling)] implement this trait.
+pub trait SliceAsReference: Sized {
+    type Raw: Sized;
+
+    /// This method does not take ownership of the slice pointed to by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to valid data for the duration of the call.
+    unsafe fn ptr_as_slice<'a>(ptr: *const CSlice<Self::Raw>) -> Result<SliceRef<'a, Self>, Error>;
+}
+
+/// # Safety
+///
+/// Only called in an auto-generated context. Should not be called directly.
+pub unsafe fn owned_slice_as_ref<'a, T: PtrAsReference>(
+    ptr: *const CSlice<T::Raw>,
+) -> Result<SliceRef<'a, T>, Error> {
+    let raw = &*ptr;
+    std::slice::from_raw_parts(raw.ptr, raw.len)
+        .iter()
+        .map(|value| T::raw_as_ref(value))
+        .collect::<Result<_, _>>()
+        .map(SliceRef::Owned)
+}
+
+macro_rules! primitive_marshalled_type {
+    ($($typ:ty )*) => {
+        $(
+            impl IntoRawConversion for $typ {
+                type Raw = Self;
+                type Ptr = Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    Ok(self)
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    Ok(self)
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                type Raw = Self;
+                type Ptr = Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    Ok(raw)
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Ok(ptr)
+                }
+            }
+
+            impl PtrAsReference for $typ {
+                type Raw = Self;
+                type Ptr = Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    Ok(raw.clone())
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Ok(ptr)
+                }
+            }
+
+            impl SliceAsReference for $typ {
+                type Raw = Self;
+
+                unsafe fn ptr_as_slice<'a>(
+                    ptr: *const CSlice<Self::Raw>,
+                ) -> Result<SliceRef<'a, Self>, Error> {
+                    Ok(SliceRef::Borrowed(<&[$typ]>::ptr_as_ref(ptr)?))
+                }
+            }
+
+            impl<'a> PtrAsReference for &'a [$typ] {
+                type Raw = CSlice<$typ>;
+                type Ptr = *const Self::Raw;
 
                 unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
-                    Ok(std::slice::from_raw_parts(raw.ptr, raw.len as usize))
+                    Ok(std::slice::from_raw_parts(raw.ptr, raw.len))
                 }
 
                 unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
@@ -442,3 +1771,190 @@ impl PtrAsReference for bool {
         Ok(ptr != 0)
     }
 }
+
+impl IntoRawConversion for char {
+    type Raw = u32;
+    type Ptr = Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(self as Self::Raw)
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        Ok(self as Self::Ptr)
+    }
+}
+
+impl FromRawConversion for char {
+    type Raw = u32;
+    type Ptr = Self::Raw;
+
+    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+        Ok(char::try_from(raw)?)
+    }
+
+    unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+        Ok(char::try_from(ptr)?)
+    }
+}
+
+impl PtrAsReference for char {
+    type Raw = u32;
+    type Ptr = Self::Raw;
+
+    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+        Ok(char::try_from(*raw)?)
+    }
+
+    unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+        Ok(char::try_from(ptr)?)
+    }
+}
+
+// 128-bit integers have no C equivalent and are passed as decimal strings.
+macro_rules! decimal_string_marshalled_type {
+    ($($typ:ty)*) => {
+        $(
+            impl IntoRawConversion for $typ {
+                type Raw = *mut ::libc::c_char;
+                type Ptr = Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    Ok(std::ffi::CString::new(self.to_string())?.into_raw())
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    self.into_raw()
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                type Raw = *mut ::libc::c_char;
+                type Ptr = Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    Ok(std::ffi::CString::from_raw(raw).into_string()?.parse()?)
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::from_raw(ptr)
+                }
+            }
+
+            impl PtrAsReference for $typ {
+                type Raw = *mut ::libc::c_char;
+                type Ptr = Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    if raw.is_null() {
+                        return Err(Error::NullString(stringify!($typ)));
+                    }
+                    Ok(::std::ffi::CStr::from_ptr(*raw).to_str()?.parse()?)
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Self::raw_as_ref(&ptr)
+                }
+            }
+        )*
+    };
+}
+
+decimal_string_marshalled_type!(i128 u128);
+
+macro_rules! nonzero_marshalled_type {
+    ($($typ:ident $primitive:ty)*) => {
+        $(
+            impl IntoRawConversion for std::num::$typ {
+                type Raw = $primitive;
+                type Ptr = Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, Error> {
+                    Ok(self.get())
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, Error> {
+                    Ok(self.get())
+                }
+            }
+
+            impl FromRawConversion for std::num::$typ {
+                type Raw = $primitive;
+                type Ptr = Self::Raw;
+
+                unsafe fn from_raw(raw: Self::Raw) -> Result<Self, Error> {
+                    Ok(Self::try_from(raw)?)
+                }
+
+                unsafe fn from_ptr(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Ok(Self::try_from(ptr)?)
+                }
+            }
+
+            impl PtrAsReference for std::num::$typ {
+                type Raw = $primitive;
+                type Ptr = Self::Raw;
+
+                unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, Error> {
+                    Ok(Self::try_from(*raw)?)
+                }
+
+                unsafe fn ptr_as_ref(ptr: Self::Ptr) -> Result<Self, Error> {
+                    Ok(Self::try_from(ptr)?)
+                }
+            }
+        )*
+    };
+}
+
+nonzero_marshalled_type!(
+    NonZeroI8 i8
+    NonZeroI16 i16
+    NonZeroI32 i32
+    NonZeroI64 i64
+    NonZeroU8 u8
+    NonZeroU16 u16
+    NonZeroU32 u32
+    NonZeroU64 u64
+    NonZeroIsize isize
+    NonZeroUsize usize
+);
+
+impl IntoRawConversion for () {
+    type Raw = i8;
+    type Ptr = Self::Raw;
+
+    fn into_raw(self) -> Result<Self::Raw, Error> {
+        Ok(0)
+    }
+
+    fn into_ptr(self) -> Result<Self::Ptr, Error> {
+        Ok(0)
+    }
+}
+
+impl FromRawConversion for () {
+    type Raw = i8;
+    type Ptr = Self::Raw;
+
+    unsafe fn from_raw(_: Self::Raw) -> Result<Self, Error> {
+        Ok(())
+    }
+
+    unsafe fn from_ptr(_: Self::Ptr) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+impl PtrAsReference for () {
+    type Raw = i8;
+    type Ptr = Self::Raw;
+
+    unsafe fn raw_as_ref(_: &Self::Raw) -> Result<Self, Error> {
+        Ok(())
+    }
+
+    unsafe fn ptr_as_ref(_: Self::Ptr) -> Result<Self, Error> {
+        Ok(())
+    }
+}
@@ -1,57 +1,259 @@
 #![recursion_limit = "128"]
 use quote::*;
 
+pub struct Field<'a> {
+    /// Accesses the field in Rust, either the field name or the tuple index.
+    pub member: quote::Tokens,
+    /// Names the field in the generated C struct. Tuple fields are named `_0`, `_1`, ...
+    pub ident: syn::Ident,
+    /// Position of a tuple field.
+    pub index: Option<usize>,
+    pub ty: &'a syn::Type,
+}
+
+pub fn fields(fields: &syn::Fields) -> Vec<Field<'_>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match field.ident {
+            Some(ref ident) => Field {
+                member: quote! { #ident },
+                ident: *ident,
+                index: None,
+                ty: &field.ty,
+            },
+            None => {
+                let member = syn::Index::from(index);
+                Field {
+                    member: quote! { #member },
+                    ident: syn::parse_str(&format!("_{}", index)).unwrap(),
+                    index: Some(index),
+                    ty: &field.ty,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Whether an explicit enum discriminant fits in an `i32`. Only integer literals, possibly
+/// negated, can be checked; other expressions are assumed to fit.
+fn discriminant_fits_i32(discriminant: &syn::Expr) -> bool {
+    match *discriminant {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(ref int),
+            ..
+        }) => int.value() <= i32::MAX as u64,
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            ref expr,
+            ..
+        }) => match **expr {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(ref int),
+                ..
+            }) => int.value() <= i32::MAX as u64 + 1,
+            _ => true,
+        },
+        syn::Expr::Paren(syn::ExprParen { ref expr, .. }) => discriminant_fits_i32(expr),
+        _ => true,
+    }
+}
+
+/// The type parameters of a derived type. Only structs may be generic.
+pub fn type_params(derive_input: &syn::DeriveInput) -> Vec<&syn::Ident> {
+    derive_input
+        .generics
+        .params
+        .iter()
+        .map(|param| match *param {
+            syn::GenericParam::Type(ref param) => match derive_input.data {
+                syn::Data::Struct(_) => &param.ident,
+                _ => panic!("Generic enums are not supported"),
+            },
+            _ => panic!("Only type parameters are supported"),
+        })
+        .collect()
+}
+
+/// The `where` clause of an impl of a derived type, extended by `predicates` if the type is
+/// generic. Impls of generic types are bounded by what they require of their field types.
+pub fn where_clause<I>(generics: &syn::Generics, predicates: I) -> quote::Tokens
+where
+    I: IntoIterator<Item = quote::Tokens>,
+{
+    if generics.params.is_empty() {
+        return quote! {};
+    }
+    let existing = generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| where_clause.predicates.iter());
+    let predicates = predicates.into_iter();
+    quote! { where #(#existing,)* #(#predicates),* }
+}
+
+/// The double underscore keeps payload structs apart from those of a type named e.g.
+/// `Shape_Circle`.
+fn payload_typename(prefix: &str, ident: &syn::Ident, variant_ident: &syn::Ident) -> syn::Ident {
+    syn::parse_str(&format!("{}_{}__{}", prefix, ident, variant_ident)).unwrap()
+}
+
 pub fn c_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
     let ident = &derive_input.ident;
     let marshal_typename: syn::Ident = syn::parse_str(&format!("__c_{}", ident)).unwrap();
     let mut_marshal_typename: syn::Ident = syn::parse_str(&format!("__c_mut_{}", ident)).unwrap();
 
-    match derive_input.data {
+    type_params(derive_input);
+    let generics = &derive_input.generics;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let marshalling = match derive_input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unnamed(ref fields),
+            ..
+        }) if fields.unnamed.len() == 1 => {
+            // Newtypes are transparent and marshalled exactly like the wrapped type
+            let ty = &fields.unnamed[0].ty;
+            let where_into_raw = where_clause(
+                generics,
+                vec![quote! { #ty: c_marshalling::IntoRawConversion }],
+            );
+            let where_from_raw = where_clause(
+                generics,
+                vec![quote! { #ty: c_marshalling::FromRawConversion }],
+            );
+            let where_ptr_as_reference = where_clause(
+                generics,
+                vec![quote! { #ty: c_marshalling::PtrAsReference }],
+            );
+
+            quote! {
+                impl #impl_generics c_marshalling::IntoRawConversion for #ident #ty_generics
+                    #where_into_raw
+                {
+                    type Raw = <#ty as c_marshalling::IntoRawConversion>::Raw;
+                    type Ptr = <#ty as c_marshalling::IntoRawConversion>::Ptr;
+
+                    fn into_raw(self) -> Result<Self::Raw, c_marshalling::Error> {
+                        c_marshalling::IntoRawConversion::into_raw(self.0)
+                    }
+
+                    fn into_ptr(self) -> Result<Self::Ptr, c_marshalling::Error> {
+                        c_marshalling::IntoRawConversion::into_ptr(self.0)
+                    }
+                }
+
+                impl #impl_generics c_marshalling::FromRawConversion for #ident #ty_generics
+                    #where_from_raw
+                {
+                    type Raw = <#ty as c_marshalling::FromRawConversion>::Raw;
+                    type Ptr = <#ty as c_marshalling::FromRawConversion>::Ptr;
+
+                    unsafe fn from_raw(raw: Self::Raw) -> Result<Self, c_marshalling::Error> {
+                        Ok(#ident(c_marshalling::FromRawConversion::from_raw(raw)?))
+                    }
+
+                    unsafe fn from_ptr(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                        Ok(#ident(c_marshalling::FromRawConversion::from_ptr(raw)?))
+                    }
+                }
+
+                impl #impl_generics c_marshalling::PtrAsReference for #ident #ty_generics
+                    #where_ptr_as_reference
+                {
+                    type Raw = <#ty as c_marshalling::PtrAsReference>::Raw;
+                    type Ptr = <#ty as c_marshalling::PtrAsReference>::Ptr;
+
+                    unsafe fn raw_as_ref(raw: &Self::Raw) -> Result<Self, c_marshalling::Error> {
+                        Ok(#ident(c_marshalling::PtrAsReference::raw_as_ref(raw)?))
+                    }
+
+                    unsafe fn ptr_as_ref(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                        Ok(#ident(c_marshalling::PtrAsReference::ptr_as_ref(raw)?))
+                    }
+                }
+            }
+        }
         syn::Data::Struct(syn::DataStruct {
-            fields: syn::Fields::Named(ref fields),
+            fields: ref struct_fields @ syn::Fields::Named(..),
+            ..
+        })
+        | syn::Data::Struct(syn::DataStruct {
+            fields: ref struct_fields @ syn::Fields::Unnamed(..),
             ..
         }) => {
-            let marshal_type_field_declarations = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap();
-                let ty = &field.ty;
+            let fields = fields(struct_fields);
+            let marshal_type_field_declarations = fields.iter().map(|field| {
+                let ident = &field.ident;
+                let ty = field.ty;
                 quote! { #ident: <#ty as c_marshalling::PtrAsReference>::Raw }
             });
-            let mut_marshal_type_field_declarations = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap();
-                let ty = &field.ty;
+            let mut_marshal_type_field_declarations = fields.iter().map(|field| {
+                let ident = &field.ident;
+                let ty = field.ty;
                 quote! { #ident: <#ty as c_marshalling::FromRawConversion>::Raw }
             });
-            let into_raw_field_initializers = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap();
-                quote! { #ident: self.#ident.into_raw()? }
+            let into_raw_field_initializers = fields.iter().map(|field| {
+                let ident = &field.ident;
+                let member = &field.member;
+                quote! { #ident: self.#member.into_raw()? }
             });
-            let from_raw_field_initializers = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap();
-                quote! { #ident: c_marshalling::FromRawConversion::from_raw(raw.#ident)? }
+            let from_raw_field_initializers = fields.iter().map(|field| {
+                let ident = &field.ident;
+                let member = &field.member;
+                quote! { #member: c_marshalling::FromRawConversion::from_raw(raw.#ident)? }
             });
-            let raw_as_ref_field_initializers = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap();
-                quote! { #ident: c_marshalling::PtrAsReference::raw_as_ref(&raw.#ident)? }
+            let raw_as_ref_field_initializers = fields.iter().map(|field| {
+                let ident = &field.ident;
+                let member = &field.member;
+                quote! { #member: c_marshalling::PtrAsReference::raw_as_ref(&raw.#ident)? }
             });
+            let where_from_raw = where_clause(
+                generics,
+                fields.iter().map(|field| {
+                    let ty = field.ty;
+                    quote! { #ty: c_marshalling::FromRawConversion }
+                }),
+            );
+            let where_into_raw = where_clause(
+                generics,
+                fields.iter().map(|field| {
+                    let ty = field.ty;
+                    quote! {
+                        #ty: c_marshalling::FromRawConversion
+                            + c_marshalling::IntoRawConversion<
+                                Raw = <#ty as c_marshalling::FromRawConversion>::Raw>
+                    }
+                }),
+            );
+            let where_ptr_as_reference = where_clause(
+                generics,
+                fields.iter().map(|field| {
+                    let ty = field.ty;
+                    quote! { #ty: c_marshalling::PtrAsReference }
+                }),
+            );
 
             quote! {
 
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
                 #[repr(C)]
-                pub struct #marshal_typename {
+                pub struct #marshal_typename #impl_generics #where_ptr_as_reference {
                     #(#marshal_type_field_declarations),*
                 }
 
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
                 #[repr(C)]
-                pub struct #mut_marshal_typename {
+                pub struct #mut_marshal_typename #impl_generics #where_from_raw {
                     #(#mut_marshal_type_field_declarations),*
                 }
 
-                impl c_marshalling::IntoRawConversion for #ident {
-                    type Raw = #mut_marshal_typename;
+                impl #impl_generics c_marshalling::IntoRawConversion for #ident #ty_generics
+                    #where_into_raw
+                {
+                    type Raw = #mut_marshal_typename #ty_generics;
                     type Ptr = *mut Self::Raw;
 
                     fn into_raw(self) -> Result<Self::Raw, c_marshalling::Error> {
@@ -65,11 +267,13 @@ pub fn c_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
                     }
                 }
 
-                impl c_marshalling::FromRawConversion for #ident {
-                    type Raw = #mut_marshal_typename;
+                impl #impl_generics c_marshalling::FromRawConversion for #ident #ty_generics
+                    #where_from_raw
+                {
+                    type Raw = #mut_marshal_typename #ty_generics;
                     type Ptr = *mut Self::Raw;
 
-                    unsafe fn from_raw(raw: #mut_marshal_typename)
+                    unsafe fn from_raw(raw: Self::Raw)
                         -> Result<Self, c_marshalling::Error> {
                         Ok(Self {
                             #(#from_raw_field_initializers),*
@@ -81,11 +285,13 @@ pub fn c_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
                     }
                 }
 
-                impl c_marshalling::PtrAsReference for #ident {
-                    type Raw = #marshal_typename;
+                impl #impl_generics c_marshalling::PtrAsReference for #ident #ty_generics
+                    #where_ptr_as_reference
+                {
+                    type Raw = #marshal_typename #ty_generics;
                     type Ptr = *const Self::Raw;
 
-                    unsafe fn raw_as_ref(raw: &#marshal_typename)
+                    unsafe fn raw_as_ref(raw: &Self::Raw)
                         -> Result<Self, c_marshalling::Error> {
                         Ok(Self {
                             #(#raw_as_ref_field_initializers),*
@@ -98,15 +304,354 @@ pub fn c_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
                 }
             }
         }
-        syn::Data::Struct(::syn::DataStruct {
-            fields: syn::Fields::Unnamed(..),
-            ..
-        }) => panic!("Tuple-struct type not supported"),
         syn::Data::Struct(syn::DataStruct {
             fields: syn::Fields::Unit,
             ..
-        }) => panic!("Unit type not supported"),
-        syn::Data::Enum(_) => panic!("Enum type not supported"),
+        }) => quote! {
+            impl c_marshalling::IntoRawConversion for #ident {
+                type Raw = i8;
+                type Ptr = Self::Raw;
+
+                fn into_raw(self) -> Result<Self::Raw, c_marshalling::Error> {
+                    Ok(0)
+                }
+
+                fn into_ptr(self) -> Result<Self::Ptr, c_marshalling::Error> {
+                    Ok(0)
+                }
+            }
+
+            impl c_marshalling::FromRawConversion for #ident {
+                type Raw = i8;
+                type Ptr = Self::Raw;
+
+                unsafe fn from_raw(_: i8) -> Result<Self, c_marshalling::Error> {
+                    Ok(#ident)
+                }
+
+                unsafe fn from_ptr(_: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                    Ok(#ident)
+                }
+            }
+
+            impl c_marshalling::PtrAsReference for #ident {
+                type Raw = i8;
+                type Ptr = Self::Raw;
+
+                unsafe fn raw_as_ref(_: &i8) -> Result<Self, c_marshalling::Error> {
+                    Ok(#ident)
+                }
+
+                unsafe fn ptr_as_ref(_: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                    Ok(#ident)
+                }
+            }
+        },
+        syn::Data::Enum(syn::DataEnum { ref variants, .. })
+            if variants
+                .iter()
+                .all(|variant| matches!(variant.fields, syn::Fields::Unit)) =>
+        {
+            // Discriminants cross the FFI boundary as `int32_t`. Literals are checked here,
+            // anything else, e.g. under `#[repr(i64)]`, by a compile time assertion.
+            let discriminant_checks = variants.iter().map(|variant| {
+                let variant_ident = &variant.ident;
+                if let Some((_, ref discriminant)) = variant.discriminant {
+                    if !discriminant_fits_i32(discriminant) {
+                        panic!(
+                            "Discriminant of {}::{} does not fit in an i32",
+                            ident, variant_ident
+                        );
+                    }
+                }
+                let message = format!(
+                    "Discriminant of {}::{} does not fit in an i32",
+                    ident, variant_ident
+                );
+                quote! {
+                    const _: () = assert!(
+                        #ident::#variant_ident as i128 >= i32::MIN as i128
+                            && #ident::#variant_ident as i128 <= i32::MAX as i128,
+                        #message
+                    );
+                }
+            });
+            let from_discriminant_arms = variants.iter().map(|variant| {
+                let variant_ident = &variant.ident;
+                quote! {
+                    discriminant if discriminant == #ident::#variant_ident as i32 =>
+                        Ok(#ident::#variant_ident)
+                }
+            });
+
+            quote! {
+                #(#discriminant_checks)*
+
+                impl c_marshalling::IntoRawConversion for #ident {
+                    type Raw = i32;
+                    type Ptr = Self::Raw;
+
+                    fn into_raw(self) -> Result<Self::Raw, c_marshalling::Error> {
+                        Ok(self as i32)
+                    }
+
+                    fn into_ptr(self) -> Result<Self::Ptr, c_marshalling::Error> {
+                        self.into_raw()
+                    }
+                }
+
+                impl c_marshalling::FromRawConversion for #ident {
+                    type Raw = i32;
+                    type Ptr = Self::Raw;
+
+                    unsafe fn from_raw(raw: i32) -> Result<Self, c_marshalling::Error> {
+                        match raw {
+                            #(#from_discriminant_arms,)*
+                            discriminant => Err(c_marshalling::Error::InvalidDiscriminant(
+                                stringify!(#ident),
+                                discriminant,
+                            )),
+                        }
+                    }
+
+                    unsafe fn from_ptr(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                        Self::from_raw(raw)
+                    }
+                }
+
+                impl c_marshalling::PtrAsReference for #ident {
+                    type Raw = i32;
+                    type Ptr = Self::Raw;
+
+                    unsafe fn raw_as_ref(raw: &i32) -> Result<Self, c_marshalling::Error> {
+                        <Self as c_marshalling::FromRawConversion>::from_raw(*raw)
+                    }
+
+                    unsafe fn ptr_as_ref(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                        Self::raw_as_ref(&raw)
+                    }
+                }
+            }
+        }
+        syn::Data::Enum(syn::DataEnum { ref variants, .. }) => {
+            let variants: Vec<_> = variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| (index as i32, &variant.ident, fields(&variant.fields)))
+                .collect();
+            let payload_variants: Vec<_> = variants
+                .iter()
+                .filter(|variant| !variant.2.is_empty())
+                .collect();
+
+            let payload_declarations =
+                payload_variants
+                    .iter()
+                    .map(|&&(_, variant_ident, ref fields)| {
+                        let marshal_payload_typename =
+                            payload_typename("__c", ident, variant_ident);
+                        let mut_marshal_payload_typename =
+                            payload_typename("__c_mut", ident, variant_ident);
+                        let marshal_type_field_declarations = fields.iter().map(|field| {
+                            let ident = &field.ident;
+                            let ty = field.ty;
+                            quote! { #ident: <#ty as c_marshalling::PtrAsReference>::Raw }
+                        });
+                        let mut_marshal_type_field_declarations = fields.iter().map(|field| {
+                            let ident = &field.ident;
+                            let ty = field.ty;
+                            quote! { #ident: <#ty as c_marshalling::FromRawConversion>::Raw }
+                        });
+                        quote! {
+                            #[doc(hidden)]
+                            #[allow(non_snake_case, non_camel_case_types)]
+                            #[repr(C)]
+                            pub struct #marshal_payload_typename {
+                                #(#marshal_type_field_declarations),*
+                            }
+
+                            #[doc(hidden)]
+                            #[allow(non_snake_case, non_camel_case_types)]
+                            #[repr(C)]
+                            pub struct #mut_marshal_payload_typename {
+                                #(#mut_marshal_type_field_declarations),*
+                            }
+                        }
+                    });
+            let marshal_type_payload_declarations =
+                payload_variants.iter().map(|&&(_, variant_ident, _)| {
+                    let marshal_payload_typename = payload_typename("__c", ident, variant_ident);
+                    quote! { #variant_ident: *const #marshal_payload_typename }
+                });
+            let mut_marshal_type_payload_declarations =
+                payload_variants.iter().map(|&&(_, variant_ident, _)| {
+                    let mut_marshal_payload_typename =
+                        payload_typename("__c_mut", ident, variant_ident);
+                    quote! { #variant_ident: *mut #mut_marshal_payload_typename }
+                });
+            let into_raw_arms = variants.iter().map(|&(index, variant_ident, ref fields)| {
+                let bindings = fields.iter().map(|field| {
+                    let member = &field.member;
+                    let ident = &field.ident;
+                    quote! { #member: #ident }
+                });
+                let payload_initializers = payload_variants.iter().map(|&&(_, other_variant_ident, _)| {
+                    if other_variant_ident == variant_ident {
+                        let mut_marshal_payload_typename =
+                            payload_typename("__c_mut", ident, variant_ident);
+                        let into_raw_field_initializers = fields.iter().map(|field| {
+                            let ident = &field.ident;
+                            quote! { #ident: c_marshalling::IntoRawConversion::into_raw(#ident)? }
+                        });
+                        quote! {
+                            #variant_ident: Box::into_raw(Box::new(#mut_marshal_payload_typename {
+                                #(#into_raw_field_initializers),*
+                            }))
+                        }
+                    } else {
+                        quote! { #other_variant_ident: std::ptr::null_mut() }
+                    }
+                });
+                quote! {
+                    #ident::#variant_ident { #(#bindings),* } => #mut_marshal_typename {
+                        tag: #index,
+                        #(#payload_initializers),*
+                    }
+                }
+            });
+            let from_raw_arms = variants.iter().map(|&(index, variant_ident, ref fields)| {
+                if fields.is_empty() {
+                    return quote! { #index => Ok(#ident::#variant_ident {}) };
+                }
+                let from_raw_field_initializers = fields.iter().map(|field| {
+                    let member = &field.member;
+                    let ident = &field.ident;
+                    quote! { #member: c_marshalling::FromRawConversion::from_raw(payload.#ident)? }
+                });
+                quote! {
+                    #index => {
+                        let payload = *Box::from_raw(raw.#variant_ident);
+                        Ok(#ident::#variant_ident {
+                            #(#from_raw_field_initializers),*
+                        })
+                    }
+                }
+            });
+            let raw_as_ref_arms = variants.iter().map(|&(index, variant_ident, ref fields)| {
+                if fields.is_empty() {
+                    return quote! { #index => Ok(#ident::#variant_ident {}) };
+                }
+                let raw_as_ref_field_initializers = fields.iter().map(|field| {
+                    let member = &field.member;
+                    let ident = &field.ident;
+                    quote! { #member: c_marshalling::PtrAsReference::raw_as_ref(&payload.#ident)? }
+                });
+                quote! {
+                    #index => {
+                        let payload = &*raw.#variant_ident;
+                        Ok(#ident::#variant_ident {
+                            #(#raw_as_ref_field_initializers),*
+                        })
+                    }
+                }
+            });
+
+            quote! {
+                #(#payload_declarations)*
+
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                #[repr(C)]
+                pub struct #marshal_typename {
+                    tag: i32,
+                    #(#marshal_type_payload_declarations),*
+                }
+
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                #[repr(C)]
+                pub struct #mut_marshal_typename {
+                    tag: i32,
+                    #(#mut_marshal_type_payload_declarations),*
+                }
+
+                impl c_marshalling::IntoRawConversion for #ident {
+                    type Raw = #mut_marshal_typename;
+                    type Ptr = *mut Self::Raw;
+
+                    fn into_raw(self) -> Result<Self::Raw, c_marshalling::Error> {
+                        Ok(match self {
+                            #(#into_raw_arms),*
+                        })
+                    }
+
+                    fn into_ptr(self) -> Result<Self::Ptr, c_marshalling::Error> {
+                        c_marshalling::box_into_ptr(self)
+                    }
+                }
+
+                impl c_marshalling::FromRawConversion for #ident {
+                    type Raw = #mut_marshal_typename;
+                    type Ptr = *mut Self::Raw;
+
+                    unsafe fn from_raw(raw: #mut_marshal_typename)
+                        -> Result<Self, c_marshalling::Error> {
+                        match raw.tag {
+                            #(#from_raw_arms,)*
+                            tag => Err(c_marshalling::Error::InvalidDiscriminant(
+                                stringify!(#ident),
+                                tag,
+                            )),
+                        }
+                    }
+
+                    unsafe fn from_ptr(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                        c_marshalling::box_from_ptr(raw)
+                    }
+                }
+
+                impl c_marshalling::PtrAsReference for #ident {
+                    type Raw = #marshal_typename;
+                    type Ptr = *const Self::Raw;
+
+                    unsafe fn raw_as_ref(raw: &#marshal_typename)
+                        -> Result<Self, c_marshalling::Error> {
+                        match raw.tag {
+                            #(#raw_as_ref_arms,)*
+                            tag => Err(c_marshalling::Error::InvalidDiscriminant(
+                                stringify!(#ident),
+                                tag,
+                            )),
+                        }
+                    }
+
+                    unsafe fn ptr_as_ref(raw: Self::Ptr) -> Result<Self, c_marshalling::Error> {
+                       Self::raw_as_ref(&*raw)
+                    }
+                }
+            }
+        }
         syn::Data::Union(_) => panic!("Union type not supported"),
+    };
+
+    let where_slice_as_reference = where_clause(
+        generics,
+        vec![quote! { #ident #ty_generics: c_marshalling::PtrAsReference }],
+    );
+
+    quote! {
+        #marshalling
+
+        impl #impl_generics c_marshalling::SliceAsReference for #ident #ty_generics
+            #where_slice_as_reference
+        {
+            type Raw = <Self as c_marshalling::PtrAsReference>::Raw;
+
+            unsafe fn ptr_as_slice<'a>(
+                ptr: *const c_marshalling::CSlice<Self::Raw>,
+            ) -> Result<c_marshalling::SliceRef<'a, Self>, c_marshalling::Error> {
+                c_marshalling::owned_slice_as_ref(ptr)
+            }
+        }
     }
 }
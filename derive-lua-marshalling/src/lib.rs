@@ -1,65 +1,227 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "256"]
 extern crate proc_macro;
 use quote::*;
 
+/// Whether `#[lua(word)]` is among `attrs`.
+fn has_lua_attribute(attrs: &[syn::Attribute], word: &str) -> bool {
+    attrs
+        .iter()
+        .filter_map(syn::Attribute::interpret_meta)
+        .any(|meta| match meta {
+            syn::Meta::List(ref list) if list.ident == "lua" => {
+                list.nested.iter().any(|nested| match *nested {
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) => ident == word,
+                    _ => false,
+                })
+            }
+            _ => false,
+        })
+}
+
+/// Key of a field in the Lua table. Tuple fields are positional.
+fn lua_key(field: &derive_c_marshalling_library::Field) -> String {
+    match field.index {
+        Some(index) => format!("[{}]", index + 1),
+        None => field.ident.to_string(),
+    }
+}
+
+/// Accesses a field in the Lua table.
+fn lua_accessor(field: &derive_c_marshalling_library::Field) -> String {
+    match field.index {
+        Some(index) => format!("[{}]", index + 1),
+        None => format!(".{}", field.ident),
+    }
+}
+
+/// The typename of a derived type, qualified by its module so that types of the same name in
+/// different modules do not collide. Generic types are named after their type arguments as
+/// well, like `Vec_T`.
+fn lua_typename(derive_input: &syn::DeriveInput) -> quote::Tokens {
+    let ident = &derive_input.ident;
+    let typename = quote! {
+        format!("{}__{}", module_path!().replace("::", "__"), stringify!(#ident))
+    };
+    let type_params = derive_c_marshalling_library::type_params(derive_input);
+    if type_params.is_empty() {
+        return typename;
+    }
+    quote! {
+        format!("{}_{}",
+            #typename,
+            [#(<#type_params as lua_marshalling::Type>::typename()),*].join("_"))
+    }
+}
+
+/// The `where` clause of the impls of a derived type, whose field types must implement
+/// `conversion`.
+fn lua_where_clause(
+    derive_input: &syn::DeriveInput,
+    types: &[&syn::Type],
+    conversion: &quote::Tokens,
+) -> quote::Tokens {
+    let type_params = derive_c_marshalling_library::type_params(derive_input)
+        .into_iter()
+        .map(|param| quote! { #param: lua_marshalling::Type + 'static });
+    let types = types.iter().map(|ty| quote! { #ty: #conversion + 'static });
+    derive_c_marshalling_library::where_clause(
+        &derive_input.generics,
+        type_params.chain(types).collect::<Vec<_>>(),
+    )
+}
+
 fn lua_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
     let ident = &derive_input.ident;
+    let typename = lua_typename(derive_input);
+    let (impl_generics, ty_generics, _) = derive_input.generics.split_for_impl();
 
     match derive_input.data {
         syn::Data::Struct(syn::DataStruct {
-            fields: syn::Fields::Named(ref fields),
+            fields: syn::Fields::Unnamed(ref fields),
+            ..
+        }) if fields.unnamed.len() == 1 => {
+            // Newtypes are transparent and marshalled exactly like the wrapped type
+            let ty = &fields.unnamed[0].ty;
+            let where_type =
+                lua_where_clause(derive_input, &[ty], &quote! { lua_marshalling::Type });
+            let where_from_raw = lua_where_clause(
+                derive_input,
+                &[ty],
+                &quote! { lua_marshalling::FromRawConversion },
+            );
+            let where_into_raw = lua_where_clause(
+                derive_input,
+                &[ty],
+                &quote! { lua_marshalling::IntoRawConversion },
+            );
+
+            quote! {
+                impl #impl_generics lua_marshalling::Type for #ident #ty_generics #where_type {
+                    const IS_NULLABLE: bool = <#ty as lua_marshalling::Type>::IS_NULLABLE;
+
+                    fn typename() -> String {
+                        #typename
+                    }
+                    fn c_typename() -> String {
+                        <#ty as lua_marshalling::Type>::c_typename()
+                    }
+                    fn prefixed_c_typename() -> String {
+                        <#ty as lua_marshalling::Type>::prefixed_c_typename()
+                    }
+                    fn forward_declaration() -> String {
+                        <#ty as lua_marshalling::Type>::forward_declaration()
+                    }
+                    fn metatype() -> String {
+                        lua_marshalling::transparent_type_metatype::<Self, #ty>()
+                    }
+                    fn dependencies() -> lua_marshalling::Dependencies {
+                        lua_marshalling::make_dependencies::<#ty>()
+                    }
+                    fn c_function_argument() -> String {
+                        <#ty as lua_marshalling::Type>::c_function_argument()
+                    }
+                    fn c_mut_function_argument() -> String {
+                        <#ty as lua_marshalling::Type>::c_mut_function_argument()
+                    }
+                }
+
+                impl #impl_generics lua_marshalling::FromRawConversion for #ident #ty_generics
+                    #where_from_raw
+                {
+                    fn function() -> String {
+                        <#ty as lua_marshalling::FromRawConversion>::function()
+                    }
+                    fn gc() -> bool {
+                        <#ty as lua_marshalling::FromRawConversion>::gc()
+                    }
+                }
+
+                impl #impl_generics lua_marshalling::IntoRawConversion for #ident #ty_generics
+                    #where_into_raw
+                {
+                    fn function() -> String {
+                        <#ty as lua_marshalling::IntoRawConversion>::function()
+                    }
+                    fn create_pointer() -> String {
+                        <#ty as lua_marshalling::IntoRawConversion>::create_pointer()
+                    }
+                    fn create_array() -> String {
+                        <#ty as lua_marshalling::IntoRawConversion>::create_array()
+                    }
+                }
+            }
+        }
+        syn::Data::Struct(syn::DataStruct {
+            fields: ref struct_fields @ syn::Fields::Named(..),
+            ..
+        })
+        | syn::Data::Struct(syn::DataStruct {
+            fields: ref struct_fields @ syn::Fields::Unnamed(..),
             ..
         }) => {
-            let lua_c_struct_fields = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap().to_string();
-                let ty = &field.ty;
+            let fields = derive_c_marshalling_library::fields(struct_fields);
+            let lua_c_struct_fields = fields.iter().map(|field| {
+                let ident = &field.ident.to_string();
+                let ty = field.ty;
                 quote! {
                     format!("    const {typename} {ident};",
                         typename=<#ty as ::lua_marshalling::Type>::prefixed_c_typename(),
                         ident=#ident)
                 }
             });
-            let lua_table_field_initializers = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap().to_string();
-                let ty = &field.ty;
+            let lua_table_field_initializers = fields.iter().map(|field| {
+                let key = lua_key(field);
+                let ident = &field.ident.to_string();
+                let ty = field.ty;
                 quote! {
-                    format!("{ident} = ({function})(value.{ident})",
+                    format!("{key} = ({function})(value.{ident})",
+                        key = #key,
                         ident = #ident,
                         function = <#ty as lua_marshalling::FromRawConversion>::function())
                 }
             });
-            let lua_c_struct_field_initializers = fields.named.iter().map(|field| {
-                let ident = &field.ident.as_ref().unwrap().to_string();
-                let ty = &field.ty;
-                quote! { format!("({function})(value.{ident})",
-                        ident = #ident,
+            let lua_c_struct_field_initializers = fields.iter().map(|field| {
+                let accessor = lua_accessor(field);
+                let ty = field.ty;
+                quote! { format!("({function})(value{accessor})",
+                        accessor = #accessor,
                         function = <#ty as lua_marshalling::IntoRawConversion>::function())
                 }
             });
-            let lua_dependencies = fields.named.iter().map(|field| {
-                let ty = &field.ty;
+            let lua_dependencies = fields.iter().map(|field| {
+                let ty = field.ty;
                 quote! {
                     dependencies.extend(lua_marshalling::make_dependencies::<#ty>());
                 }
             });
+            // The metatype defines the conversions both ways.
+            let types: Vec<_> = fields.iter().map(|field| field.ty).collect();
+            let where_clause = lua_where_clause(
+                derive_input,
+                &types,
+                &quote! { lua_marshalling::FromRawConversion + lua_marshalling::IntoRawConversion },
+            );
 
             quote! {
-                impl lua_marshalling::Type for #ident {
+                impl #impl_generics lua_marshalling::Type for #ident #ty_generics #where_clause {
                     fn typename() -> String {
-                        stringify!(#ident).to_string()
+                        #typename
                     }
                     fn typedeclaration() -> String {
                         let fields: &[String] = &[
                             #(#lua_c_struct_fields),*
                         ];
                                                 format!(r#"
-            typedef struct {{
+            typedef struct {self_typename} {{
                 {fields}
             }} {self_typename};
             "#,
                             fields = fields.join("\n"),
                             self_typename = Self::prefixed_typename())
                     }
+                    fn forward_declaration() -> String {
+                        lua_marshalling::struct_type_forward_declaration::<Self>()
+                    }
                     fn dependencies() -> lua_marshalling::Dependencies {
                         let mut dependencies = lua_marshalling::Dependencies::new();
                         #(#lua_dependencies)*
@@ -72,13 +234,7 @@ fn lua_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
                         format!("{}*", Self::prefixed_typename())
                     }
                     fn metatype() -> String {
-                        lua_marshalling::ptr_type_metatype::<Self>()
-                    }
-                }
-
-                impl lua_marshalling::FromRawConversion for #ident {
-                    fn function() -> String {
-                        format!(
+                        let from_c = format!(
                                                     r#"function(value)
                 return {{
                     {}
@@ -86,25 +242,348 @@ fn lua_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
             end"#,
                             &[
                                 #(#lua_table_field_initializers),*
-                            ].join(", "))
+                            ].join(", "));
+                        let fields: &[String] = &[
+                            #(#lua_c_struct_field_initializers),*
+                        ];
+                                                let into_c = format!(r#"function(value)
+                return __typename_{self_typename}(
+                    {fields}
+                )
+            end"#,
+                            self_typename = Self::typename(),
+                            fields = fields.join(",\n    "));
+                        format!("{}{}",
+                            lua_marshalling::ptr_type_metatype::<Self>(),
+                            lua_marshalling::named_conversions_metatype::<Self>(&from_c, &into_c))
+                    }
+                }
+
+                impl #impl_generics lua_marshalling::FromRawConversion for #ident #ty_generics
+                    #where_clause
+                {
+                    fn function() -> String {
+                        lua_marshalling::named_from_raw_function::<Self>()
                     }
                     fn gc() -> bool {
                         true
                     }
                 }
 
+                impl #impl_generics lua_marshalling::IntoRawConversion for #ident #ty_generics
+                    #where_clause
+                {
+                    fn function() -> String {
+                        lua_marshalling::named_into_raw_function::<Self>()
+                    }
+                    fn create_pointer() -> String {
+                        lua_marshalling::ptr_type_create_pointer::<Self>()
+                    }
+                    fn create_array() -> String {
+                        lua_marshalling::immediate_type_create_array::<Self>()
+                    }
+                }
+            }
+        }
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Unit,
+            ..
+        }) => quote! {
+            impl lua_marshalling::Type for #ident {
+                fn typename() -> String {
+                    #typename
+                }
+                fn typedeclaration() -> String {
+                    format!("typedef int8_t {};", Self::prefixed_typename())
+                }
+                fn c_function_argument() -> String {
+                    Self::prefixed_c_typename()
+                }
+                fn c_mut_function_argument() -> String {
+                    Self::prefixed_c_typename()
+                }
+                fn metatype() -> String {
+                    lua_marshalling::primitive_type_metatype::<Self>()
+                }
+            }
+
+            impl lua_marshalling::FromRawConversion for #ident {
+                fn function() -> String {
+                    "function(value) return true end".to_owned()
+                }
+                fn gc() -> bool {
+                    false
+                }
+            }
+
+            impl lua_marshalling::IntoRawConversion for #ident {
+                fn function() -> String {
+                    "function(value) return 0 end".to_owned()
+                }
+                fn create_pointer() -> String {
+                    lua_marshalling::immediate_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    lua_marshalling::immediate_type_create_array::<Self>()
+                }
+            }
+        },
+        syn::Data::Enum(syn::DataEnum { ref variants, .. })
+            if variants
+                .iter()
+                .all(|variant| matches!(variant.fields, syn::Fields::Unit)) =>
+        {
+            let as_integer = has_lua_attribute(&derive_input.attrs, "integer");
+            let lua_variants = variants.iter().map(|variant| {
+                let variant_ident = &variant.ident;
+                let name = variant_ident.to_string();
+                quote! { (#name, #ident::#variant_ident as i32) }
+            });
+
+            quote! {
+                impl lua_marshalling::Type for #ident {
+                    fn typename() -> String {
+                        #typename
+                    }
+                    fn typedeclaration() -> String {
+                        format!("typedef int32_t {};", Self::prefixed_typename())
+                    }
+                    fn c_function_argument() -> String {
+                        Self::prefixed_c_typename()
+                    }
+                    fn c_mut_function_argument() -> String {
+                        Self::prefixed_c_typename()
+                    }
+                    fn exported_name() -> Option<String> {
+                        Some(stringify!(#ident).to_owned())
+                    }
+                    fn metatype() -> String {
+                        lua_marshalling::enum_type_metatype::<Self>(
+                            stringify!(#ident),
+                            &[#(#lua_variants),*],
+                            #as_integer)
+                    }
+                }
+
+                impl lua_marshalling::FromRawConversion for #ident {
+                    fn function() -> String {
+                        lua_marshalling::enum_type_from_raw_function::<Self>()
+                    }
+                    fn gc() -> bool {
+                        false
+                    }
+                }
+
                 impl lua_marshalling::IntoRawConversion for #ident {
                     fn function() -> String {
+                        lua_marshalling::enum_type_into_raw_function::<Self>(stringify!(#ident))
+                    }
+                    fn create_pointer() -> String {
+                        lua_marshalling::immediate_type_create_pointer::<Self>()
+                    }
+                    fn create_array() -> String {
+                        lua_marshalling::immediate_type_create_array::<Self>()
+                    }
+                }
+            }
+        }
+        syn::Data::Enum(syn::DataEnum { ref variants, .. }) => {
+            let variants: Vec<_> = variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| {
+                    (
+                        index as i32,
+                        variant.ident.to_string(),
+                        derive_c_marshalling_library::fields(&variant.fields),
+                    )
+                })
+                .collect();
+            let payload_variants: Vec<_> = variants
+                .iter()
+                .filter(|variant| !variant.2.is_empty())
+                .collect();
+
+            let lua_payload_typedeclarations =
+                payload_variants.iter().map(|&(_, variant, fields)| {
+                    let lua_c_struct_fields = fields.iter().map(|field| {
+                        let ident = &field.ident.to_string();
+                        let ty = field.ty;
+                        quote! {
+                            format!("    const {typename} {ident};",
+                                typename=<#ty as ::lua_marshalling::Type>::prefixed_c_typename(),
+                                ident=#ident)
+                        }
+                    });
+                    quote! {
+                        format!(r#"typedef struct {{
+{fields}
+}} {payload_typename};"#,
+                            fields = [#(#lua_c_struct_fields),*].join("\n"),
+                            payload_typename =
+                                lua_marshalling::prefixed_enum_payload_typename::<Self>(#variant))
+                    }
+                });
+            let lua_c_struct_payloads = payload_variants.iter().map(|&(_, variant, _)| {
+                quote! {
+                    format!("    const {payload_typename} *{variant};",
+                        payload_typename =
+                            lua_marshalling::prefixed_enum_payload_typename::<Self>(#variant),
+                        variant = #variant)
+                }
+            });
+            let lua_payload_metatypes = payload_variants.iter().map(|&(_, variant, _)| {
+                quote! {
+                    format!(r#"__typename_{payload_typename} = ffi.metatype("{c_payload_typename}", {{}})"#,
+                        payload_typename = lua_marshalling::enum_payload_typename::<Self>(#variant),
+                        c_payload_typename =
+                            lua_marshalling::prefixed_enum_payload_typename::<Self>(#variant))
+                }
+            });
+            let lua_table_variants = variants.iter().map(|&(index, ref variant, ref fields)| {
+                let lua_table_field_initializers = fields.iter().map(|field| {
+                    let key = lua_key(field);
+                    let ident = &field.ident.to_string();
+                    let ty = field.ty;
+                    quote! {
+                        format!("{key} = ({function})(value.{variant}.{ident})",
+                            key = #key,
+                            variant = #variant,
+                            ident = #ident,
+                            function = <#ty as lua_marshalling::FromRawConversion>::function())
+                    }
+                });
+                quote! {
+                    format!("value.tag == {index} then\n        return {{ {fields} }}",
+                        index = #index,
+                        fields = [
+                            format!("tag = {:?}", #variant),
+                            #(#lua_table_field_initializers),*
+                        ].join(", "))
+                }
+            });
+            let lua_c_struct_variants = variants.iter().map(|&(index, ref variant, ref fields)| {
+                let lua_c_struct_payload_initializers = payload_variants.iter().map(|&(_, other_variant, _)| {
+                    if other_variant != variant {
+                        return quote! { "nil".to_owned() };
+                    }
+                    let lua_c_struct_field_initializers = fields.iter().map(|field| {
+                        let accessor = lua_accessor(field);
+                        let ty = field.ty;
+                        quote! {
+                            format!("({function})(value{accessor})",
+                                accessor = #accessor,
+                                function = <#ty as lua_marshalling::IntoRawConversion>::function())
+                        }
+                    });
+                    quote! {
+                        format!(r#"__typename_{payload_typename}(
+                {fields})"#,
+                            payload_typename =
+                                lua_marshalling::enum_payload_typename::<Self>(#variant),
+                            fields = [#(#lua_c_struct_field_initializers),*].join(",\n                "))
+                    }
+                });
+                quote! {
+                    format!(r#"value.tag == {variant:?} then
+        return __typename_{self_typename}(
+            {index},
+            {payloads})"#,
+                        variant = #variant,
+                        self_typename = <Self as lua_marshalling::Type>::typename(),
+                        index = #index,
+                        payloads = [
+                            #(#lua_c_struct_payload_initializers),*
+                        ].join(",\n            "))
+                }
+            });
+            let lua_dependencies = variants
+                .iter()
+                .flat_map(|(_, _, fields)| fields)
+                .map(|field| {
+                    let ty = field.ty;
+                    quote! {
+                        dependencies.extend(lua_marshalling::make_dependencies::<#ty>());
+                    }
+                });
+
+            quote! {
+                impl lua_marshalling::Type for #ident {
+                    fn typename() -> String {
+                        #typename
+                    }
+                    fn typedeclaration() -> String {
+                        let payloads: &[String] = &[
+                            #(#lua_payload_typedeclarations),*
+                        ];
                         let fields: &[String] = &[
-                            #(#lua_c_struct_field_initializers),*
+                            #(#lua_c_struct_payloads),*
                         ];
-                                                format!(r#"function(value)
-                return __typename_{self_typename}(
-                    {fields}
-                )
-            end"#,
-                            self_typename = <Self as lua_marshalling::Type>::typename(),
-                            fields = fields.join(",\n    "))
+                        format!(r#"{payloads}
+typedef struct {self_typename} {{
+    const int32_t tag;
+{fields}
+}} {self_typename};"#,
+                            payloads = payloads.join("\n"),
+                            fields = fields.join("\n"),
+                            self_typename = Self::prefixed_typename())
+                    }
+                    fn forward_declaration() -> String {
+                        lua_marshalling::struct_type_forward_declaration::<Self>()
+                    }
+                    fn dependencies() -> lua_marshalling::Dependencies {
+                        let mut dependencies = lua_marshalling::Dependencies::new();
+                        #(#lua_dependencies)*
+                        dependencies
+                    }
+                    fn c_function_argument() -> String {
+                        format!("const {}*", Self::prefixed_c_typename())
+                    }
+                    fn c_mut_function_argument() -> String {
+                        format!("{}*", Self::prefixed_typename())
+                    }
+                    fn metatype() -> String {
+                        let payloads: &[String] = &[
+                            #(#lua_payload_metatypes),*
+                        ];
+                        let variants: &[String] = &[
+                            #(#lua_table_variants),*
+                        ];
+                        let from_c = format!(r#"function(value)
+    if {variants}
+    end
+end"#,
+                            variants = variants.join("\n    elseif "));
+                        let variants: &[String] = &[
+                            #(#lua_c_struct_variants),*
+                        ];
+                        let into_c = format!(r#"function(value)
+    if {variants}
+    else
+        error("Invalid tag for {name}: "..tostring(value.tag))
+    end
+end"#,
+                            variants = variants.join("\n    elseif "),
+                            name = stringify!(#ident));
+                        format!("{}{}\n{}",
+                            lua_marshalling::ptr_type_metatype::<Self>(),
+                            payloads.join("\n"),
+                            lua_marshalling::named_conversions_metatype::<Self>(&from_c, &into_c))
+                    }
+                }
+
+                impl lua_marshalling::FromRawConversion for #ident {
+                    fn function() -> String {
+                        lua_marshalling::named_from_raw_function::<Self>()
+                    }
+                    fn gc() -> bool {
+                        true
+                    }
+                }
+
+                impl lua_marshalling::IntoRawConversion for #ident {
+                    fn function() -> String {
+                        lua_marshalling::named_into_raw_function::<Self>()
                     }
                     fn create_pointer() -> String {
                         lua_marshalling::ptr_type_create_pointer::<Self>()
@@ -115,17 +594,28 @@ fn lua_marshalling(derive_input: &syn::DeriveInput) -> quote::Tokens {
                 }
             }
         }
-        _ => panic!("Only non-tuple struct supported"),
+        syn::Data::Union(_) => panic!("Union type not supported"),
     }
 }
 
-#[proc_macro_derive(LuaMarshalling)]
+#[proc_macro_derive(LuaMarshalling, attributes(lua))]
 pub fn derive_lua_marshalling(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let c = derive_c_marshalling_library::c_marshalling(&syn::parse(input.clone()).unwrap());
-    let lua = lua_marshalling(&syn::parse(input).unwrap());
+    let derive_input: syn::DeriveInput = syn::parse(input).unwrap();
+    let lua = lua_marshalling(&derive_input);
+    let ident = &derive_input.ident;
+    let (impl_generics, ty_generics, _) = derive_input.generics.split_for_impl();
+    let where_slice_element = derive_c_marshalling_library::where_clause(
+        &derive_input.generics,
+        vec![quote! { #ident #ty_generics: lua_marshalling::IntoRawConversion }],
+    );
     let quote = quote! {
         #lua
 
+        impl #impl_generics lua_marshalling::SliceElement for #ident #ty_generics
+            #where_slice_element
+        {}
+
         #c
     };
     quote.into()
@@ -3,6 +3,12 @@ use quote::*;
 use std::fs::File;
 use std::io::Read;
 
+/// Every type has a handful of helpers, e.g. `__typename_X`, and bindings with many types
+/// would exceed the 200 locals a Lua chunk may have. The helpers are therefore globals of a
+/// private environment of the module chunk, which still sees the globals of `_G` but does
+/// not leak into it.
+const PRIVATE_ENVIRONMENT: &str = "setfenv(1, setmetatable({}, { __index = _G }))\n";
+
 fn function_declarations(
     functions: &[parser::Function],
     uses: &[quote::Tokens],
@@ -10,18 +16,35 @@ fn function_declarations(
     ffi_load_using_cpath: bool,
 ) -> quote::Tokens {
     let extern_lua_ffi_c_header_functions = functions.iter().map(|function| {
-        let ident = function.ident.to_string();
+        let ident = function.symbol();
         let mut argument_declaration: Vec<_> = function
             .args
             .iter()
             .map(|arg| {
-                let typ = &arg.typ;
+                let typ = arg.marshalled_typ();
+                if arg.receiver {
+                    return quote! { "const void *".to_owned() };
+                }
                 quote! {
                     <#typ as lua_marshalling::Type>::c_function_argument()
                 }
             })
             .collect();
-        let ret = &function.ret;
+        let ret = match function.ret {
+            Some(ref ret) => ret,
+            None => {
+                return quote! {
+                    format!(r#"int32_t {ident}(
+                {argument_declaration});"#,
+                        ident=#ident,
+                        argument_declaration={
+                            let argument_declaration: Vec<String> =
+                                vec![#(#argument_declaration),*];
+                            argument_declaration
+                        }.join(",\n    "))
+                }
+            }
+        };
         argument_declaration.push(quote! {
             format!("{}*", <#ret as lua_marshalling::Type>::c_mut_function_argument())
         });
@@ -38,7 +61,8 @@ fn function_declarations(
     });
 
     let extern_lua_function_wrappers = functions.iter().map(|function| {
-        let ident = function.ident.to_string();
+        let ident = function.symbol();
+        let lua_name = function.lua_name();
         let argument_declaration: Vec<_> = function
             .args
             .iter()
@@ -49,7 +73,18 @@ fn function_declarations(
             .iter()
             .map(|arg| {
                 let ident = arg.ident.to_string();
-                let typ = &arg.typ;
+                let typ = arg.marshalled_typ();
+                if arg.callback.is_some() {
+                    return quote! { format!("__callback_{}", #ident) };
+                }
+                if arg.receiver {
+                    return quote! {
+                        format!(
+                            "({function})({ident})",
+                            ident=#ident,
+                            function=(&lua_marshalling::Receiver::<#typ>::new()).function())
+                    };
+                }
                 quote! {
                     format!(
                         "({function})({ident})",
@@ -58,20 +93,100 @@ fn function_declarations(
                 }
             })
             .collect();
+        // Callbacks are cast for the duration of the call. The call is protected, so that the
+        // casts are freed even if converting the other arguments raises an error, and the
+        // error raised by a callback is raised again if the call failed with it, status 3.
+        let callback_casts: Vec<_> = function
+            .args
+            .iter()
+            .filter(|arg| arg.callback.is_some())
+            .map(|arg| {
+                let ident = arg.ident.to_string();
+                let typ = arg.marshalled_typ();
+                quote! {
+                    format!(
+                        "local __callback_{ident} = ({function})({ident}, __callback_errors)\n    ",
+                        ident=#ident,
+                        function=<#typ as lua_marshalling::IntoRawConversion>::function())
+                }
+            })
+            .collect();
+        let has_callbacks = !callback_casts.is_empty();
+        let callback_casts = quote! {
+            {
+                let casts: &[String] = &[#(#callback_casts),*];
+                if casts.is_empty() {
+                    String::new()
+                } else {
+                    format!("local __callback_errors = {{}}\n    {}", casts.concat())
+                }
+            }
+        };
+        let (call_status, call_begin, call_end) = if has_callbacks {
+            ("ok, status", "pcall(function() return ", " end)")
+        } else {
+            ("status", "", "")
+        };
+        let mut callback_frees: String = function
+            .args
+            .iter()
+            .filter(|arg| arg.callback.is_some())
+            .map(|arg| format!("__callback_{}:free()\n    ", arg.ident))
+            .collect();
+        if has_callbacks {
+            callback_frees.push_str(
+                r#"if not ok then
+        error(status, 0)
+    end
+    if status == 3 and __callback_errors.last ~= nil then
+        error(__callback_errors.last, 0)
+    end
+    "#,
+            );
+        }
 
-        let ret = &function.ret;
         let argument_declaration = argument_declaration.join(",\n    ");
+        let ret = match function.ret {
+            Some(ref ret) => ret,
+            None => {
+                return quote! {
+                    format!(r#"function M.{lua_name}(
+    {argument_declaration})
+    {callback_casts}local {call_status} = {call_begin}rust.{ident}(
+        {argument_passing}
+    ){call_end}
+    {callback_frees}if status ~= 0 then
+        error("{lua_name} failed with status "..status)
+    end
+end
+"#,
+                        ident = #ident,
+                        lua_name = #lua_name,
+                        argument_declaration = #argument_declaration,
+                        callback_casts = #callback_casts,
+                        callback_frees = #callback_frees,
+                        call_status = #call_status,
+                        call_begin = #call_begin,
+                        call_end = #call_end,
+                        argument_passing = {
+                            let argument_passing: Vec<String> = vec![#(#argument_passing),*];
+                            argument_passing
+                        }.join(",\n    ")
+                    )
+                }
+            }
+        };
 
         quote! {
-            format!(r#"function M.{ident}(
+            format!(r#"function M.{lua_name}(
     {argument_declaration})
     local __typeof = __c_mut_function_argument_{typename}
     local __ret_ptr = __typeof(1, {{}})
-    local status = rust.{ident}(
+    {callback_casts}local {call_status} = {call_begin}rust.{ident}(
         {argument_passing}
-    )
-    if status ~= 0 then
-        error("{ident} failed with status "..status)
+    ){call_end}
+    {callback_frees}if status ~= 0 then
+        error("{lua_name} failed with status "..status)
     end
     local __ret = __ret_ptr[0]
     {gc}
@@ -80,7 +195,13 @@ fn function_declarations(
 end
 "#,
                 ident = #ident,
+                lua_name = #lua_name,
                 argument_declaration = #argument_declaration,
+                callback_casts = #callback_casts,
+                callback_frees = #callback_frees,
+                call_status = #call_status,
+                call_begin = #call_begin,
+                call_end = #call_end,
                 typename = <#ret as lua_marshalling::Type>::typename(),
                 argument_passing = {
                     let mut argument_passing: Vec<String> = [#(#argument_passing),*].to_vec();
@@ -92,23 +213,55 @@ end
                 } else {
                     "".to_owned()
                 },
-                function = <#ret as lua_marshalling::FromRawConversion>::function()
+                function = <#ret as lua_marshalling::FromRawConversion>::return_function()
             )
         }
     });
 
+    // The tables holding the functions of `impl` blocks, by type. Handles of types with
+    // methods index them, allowing `handle:method()`.
+    let mut owners = std::collections::BTreeMap::new();
+    for function in functions {
+        if let Some(ref owner) = function.owner {
+            let has_receiver = function.args.iter().any(|arg| arg.receiver);
+            let entry = owners
+                .entry(owner.ident.to_string())
+                .or_insert((&owner.typ, false));
+            entry.1 |= has_receiver;
+        }
+    }
+    let extern_lua_owner_tables = owners.iter().map(|(ident, &(typ, has_receiver))| {
+        if has_receiver {
+            quote! {
+                format!(
+                    "M.{ident} = M.{ident} or {{}}\n{metatype}",
+                    ident = #ident,
+                    metatype = (&lua_marshalling::Receiver::<#typ>::new()).methods_metatype(#ident))
+            }
+        } else {
+            quote! {
+                format!("M.{ident} = M.{ident} or {{}}\n", ident = #ident)
+            }
+        }
+    });
+
     let extern_lua_unique_types = functions.iter().map(|function| {
         let args = function.args.iter().map(|arg| {
-            let typ = &arg.typ;
+            let typ = arg.marshalled_typ();
+            if arg.receiver {
+                return quote! {
+                    (&lua_marshalling::Receiver::<#typ>::new()).dependencies()
+                };
+            }
             quote! {
                 lua_marshalling::make_dependencies::<#typ>()
             }
         });
 
-        let ret = &function.ret;
+        let ret = function.ret.iter();
         quote! {
             #(#args,)*
-            lua_marshalling::make_dependencies::<#ret>(),
+            #(lua_marshalling::make_dependencies::<#ret>(),)*
         }
     });
 
@@ -124,30 +277,50 @@ end
         format!("ffi.load('{library_name}')", library_name = library_name)
     };
 
+    let receivers = if owners.values().any(|&(_, has_receiver)| has_receiver) {
+        // Only one of them is used if all receivers are of the same kind.
+        quote! {
+            #[allow(unused_imports)]
+            use lua_marshalling::{HandleReceiver as _, ValueReceiver as _};
+        }
+    } else {
+        quote! {}
+    };
+
     quote! {
         #[doc(hidden)]
+        // Receivers are borrowed explicitly to pick their kind.
+        #[allow(clippy::needless_borrow)]
         pub mod lua_bootstrap {
             #(#uses)*
+            #receivers
 
-            #[no_mangle]
-            pub extern "C" fn __lua_bootstrap() -> *mut libc::c_char {
+            fn __bootstrap_module() -> Result<String, String> {
                 let unique_types: lua_marshalling::Dependencies =
                     [ #(#extern_lua_unique_types)* ]
                         .iter()
-                        .flat_map(|value| value.into_iter()
-                            .map(|(k, v)| (k.clone(), v.clone())))
+                        .flat_map(|value| value.iter()
+                            .map(|(k, v)| (*k, v.clone())))
                         .collect();
                 let sorted_types =
                     lua_marshalling::dependency_sorted_type_descriptions(&unique_types);
+                lua_marshalling::check_exported_names(&sorted_types)?;
 
-                std::ffi::CString::new(
-                    [
+                Ok([
                         r#"-- Code generated by Rust Lua interface. DO NOT EDIT.
 
     local ffi = require("ffi")
 
     ffi.cdef[[
     "#.to_owned(),
+                        sorted_types
+                            .iter()
+                            .map(|dependencies| (dependencies.forward_declaration)())
+                            .filter(|forward_declaration| !forward_declaration.is_empty())
+                            .collect::<std::collections::BTreeSet<_>>()
+                            .into_iter()
+                            .collect::<Vec<_>>()
+                            .join("\n"),
                         sorted_types
                             .iter()
                             .map(|dependencies| (dependencies.typedeclaration)())
@@ -165,20 +338,45 @@ end
 
     local M = {{}}
 
-    "#, ffi_load_expression = #ffi_load_expression),
+    -- Stands in for `nil` in arrays, which cannot hold `nil`, e.g. in a `Vec<Option<T>>`.
+    M.null = setmetatable({{}}, {{ __tostring = function() return "null" end }})
+
+    {private_environment}
+    "#,
+                            ffi_load_expression = #ffi_load_expression,
+                            private_environment = #PRIVATE_ENVIRONMENT),
                         sorted_types
                             .iter()
                             .map(|dependencies| (dependencies.metatype)())
                             .collect::<Vec<String>>()
                             .join("\n"),
+                        #(#extern_lua_owner_tables,)*
                         #(#extern_lua_function_wrappers,)*
                         r#"
     return M
     "#.to_owned()
-                    ].join("\n"))
-                        .ok()
-                        .map(std::ffi::CString::into_raw)
-                        .unwrap_or_else(std::ptr::null_mut)
+                ].join("\n"))
+            }
+
+            /// Returns the generated Lua module, or null with the reason in `error`, which is
+            /// freed with `__free_lua_bootstrap` as well.
+            ///
+            /// # Safety
+            ///
+            /// `error` must be valid for writes.
+            #[no_mangle]
+            pub unsafe extern "C" fn __lua_bootstrap(
+                error: *mut *mut libc::c_char,
+            ) -> *mut libc::c_char {
+                match __bootstrap_module().and_then(|bootstrap| {
+                    std::ffi::CString::new(bootstrap).map_err(|err| err.to_string())
+                }) {
+                    Ok(bootstrap) => bootstrap.into_raw(),
+                    Err(err) => {
+                        *error = std::ffi::CString::new(err).unwrap_or_default().into_raw();
+                        std::ptr::null_mut()
+                    }
+                }
             }
 
             /// # Safety
@@ -186,8 +384,8 @@ end
             /// Only called in an auto-generated context. Should not be called directly.
             #[no_mangle]
             pub unsafe extern "C" fn __free_lua_bootstrap(bootstrap: *mut ::libc::c_char) {
-                if bootstrap != std::ptr::null_mut() {
-                    std::ffi::CString::from_raw(bootstrap);
+                if !bootstrap.is_null() {
+                    drop(std::ffi::CString::from_raw(bootstrap));
                 }
             }
         }
@@ -215,7 +413,7 @@ pub fn generate(
 {}
 {}
 "#,
-        parser::function_declarations(&functions, &uses).to_string(),
-        function_declarations(&functions, &uses, library_name, ffi_load_using_cpath).to_string()
+        parser::function_declarations(&functions, &uses),
+        function_declarations(&functions, &uses, library_name, ffi_load_using_cpath)
     )
 }
@@ -8,22 +8,8 @@ pub use derive_lua_marshalling::*;
 fn is_valid_type_prefix(string: &str) -> bool {
     string.is_empty() || {
         let mut bytes = string.as_bytes().iter();
-        (match bytes.next().unwrap() {
-            #[rustfmt::skip]
-            | b'a'..=b'z'
-            | b'A'..=b'Z'
-            | b'_'
-            => true,
-            _ => false,
-        }) && bytes.all(|byte| match *byte {
-            #[rustfmt::skip]
-            | b'a'..=b'z'
-            | b'A'..=b'Z'
-            | b'0'..=b'9'
-            | b'_'
-            => true,
-            _ => false,
-        })
+        matches!(bytes.next().unwrap(), b'a'..=b'z' | b'A'..=b'Z' | b'_')
+            && bytes.all(|byte| matches!(*byte, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_'))
     }
 }
 
@@ -45,13 +31,11 @@ fn prefixed(string: &str) -> String {
                 .map(|name| {
                     assert!(
                         is_valid_type_prefix(&name),
-                        format!(
-                            "Invalid value ({:#?}) supplied for \
-                             RUST_LUA_FFI_TYPE_PREFIX. Value must contain only \
-                             ASCII alpha-numeric characters or underscores and \
-                             cannot start with a number",
-                            name,
-                        )
+                        "Invalid value ({:#?}) supplied for \
+                         RUST_LUA_FFI_TYPE_PREFIX. Value must contain only \
+                         ASCII alpha-numeric characters or underscores and \
+                         cannot start with a number",
+                        name,
                     );
                     format!("{}__", name)
                 })
@@ -67,13 +51,23 @@ pub struct TypeDescription {
     pub typeid: std::any::TypeId,
     pub dependencies: std::collections::HashSet<std::any::TypeId>,
     pub typedeclaration: fn() -> String,
+    pub forward_declaration: fn() -> String,
+    pub is_indirect: bool,
     pub metatype: fn() -> String,
+    pub typename: fn() -> String,
+    pub exported_name: fn() -> Option<String>,
 }
 
 pub trait Type {
     // Defaults to false since all derived types outside of this module _won't_
     // be primitive.
     const IS_PRIMITIVE: bool = false;
+    // Types which refer to their dependencies only through pointers, such as `Vec<T>`, only
+    // need forward declarations of them. This is what allows recursive types.
+    const IS_INDIRECT: bool = false;
+    // Types which may be `nil` in Lua. Arrays of them may have holes and are therefore passed
+    // with an explicit length `n`.
+    const IS_NULLABLE: bool = false;
 
     fn typename() -> String;
     fn c_typename() -> String {
@@ -82,6 +76,10 @@ pub trait Type {
     fn typedeclaration() -> String {
         "".to_owned()
     }
+    /// Declares the C type ahead of `typedeclaration`, if it can be.
+    fn forward_declaration() -> String {
+        "".to_owned()
+    }
     fn metatype() -> String {
         if Self::IS_PRIMITIVE {
             primitive_type_metatype::<Self>()
@@ -92,6 +90,10 @@ pub trait Type {
     fn dependencies() -> Dependencies {
         vec![].into_iter().collect()
     }
+    /// The name of the table the type exports as `M.{name}`, if any.
+    fn exported_name() -> Option<String> {
+        None
+    }
     fn c_function_argument() -> String;
     fn c_mut_function_argument() -> String;
     fn prefixed_typename() -> String {
@@ -112,6 +114,10 @@ pub trait Type {
 
 pub trait FromRawConversion: Type {
     fn function() -> String;
+    /// Converts the value returned by a function, which may be several Lua values.
+    fn return_function() -> String {
+        Self::function()
+    }
     fn gc() -> bool;
 }
 
@@ -123,34 +129,119 @@ pub trait IntoRawConversion: Type {
 
 pub type Dependencies = std::collections::HashMap<std::any::TypeId, TypeDescription>;
 
+thread_local! {
+    // The types whose dependencies are being made, with their direct dependencies so far.
+    static MAKING_DEPENDENCIES: std::cell::RefCell<Vec<(std::any::TypeId, std::collections::HashSet<std::any::TypeId>)>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
 pub fn make_dependencies<T: Type + 'static>() -> Dependencies {
     let typeid = std::any::TypeId::of::<T>();
+    let is_recursive = MAKING_DEPENDENCIES.with(|making| {
+        let mut making = making.borrow_mut();
+        if let Some((_, type_dependencies)) = making.last_mut() {
+            type_dependencies.insert(typeid);
+        }
+        let is_recursive = making
+            .iter()
+            .any(|&(making_typeid, _)| making_typeid == typeid);
+        if !is_recursive {
+            making.push((typeid, std::collections::HashSet::new()));
+        }
+        is_recursive
+    });
+    if is_recursive {
+        // The outer call for `T` adds its description
+        return Dependencies::new();
+    }
     let mut dependencies = T::dependencies();
-    let type_dependencies = dependencies.keys().cloned().collect();
+    let (_, type_dependencies) =
+        MAKING_DEPENDENCIES.with(|making| making.borrow_mut().pop().unwrap());
     dependencies.insert(
         typeid,
         TypeDescription {
             typeid: std::any::TypeId::of::<T>(),
             dependencies: type_dependencies,
             typedeclaration: T::typedeclaration,
+            forward_declaration: T::forward_declaration,
+            is_indirect: T::IS_INDIRECT,
             metatype: T::metatype,
+            typename: T::typename,
+            exported_name: T::exported_name,
         },
     );
     dependencies
 }
 
-pub fn dependency_sorted_type_descriptions<'a>(
-    dependencies: &'a Dependencies,
-) -> Vec<&'a TypeDescription> {
+/// Reports types exporting tables of the same name, such as fieldless enums of the same name
+/// in different modules, which would overwrite each other.
+pub fn check_exported_names(descriptions: &[&TypeDescription]) -> Result<(), String> {
+    let mut exported = std::collections::BTreeMap::new();
+    for description in descriptions {
+        if let Some(name) = (description.exported_name)() {
+            let typename = (description.typename)();
+            if let Some(other) = exported.insert(name.clone(), typename.clone()) {
+                let mut typenames = [other, typename];
+                typenames.sort();
+                return Err(format!(
+                    "{} and {} are both exported as M.{}",
+                    typenames[0], typenames[1], name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_exported_names() {
+    fn description(
+        typename: fn() -> String,
+        exported_name: fn() -> Option<String>,
+    ) -> TypeDescription {
+        TypeDescription {
+            typeid: std::any::TypeId::of::<()>(),
+            dependencies: std::collections::HashSet::new(),
+            typedeclaration: String::new,
+            forward_declaration: String::new,
+            is_indirect: false,
+            metatype: String::new,
+            typename,
+            exported_name,
+        }
+    }
+    let v1 = description(|| "v1__Method".to_owned(), || Some("Method".to_owned()));
+    let v2 = description(|| "v2__Method".to_owned(), || Some("Method".to_owned()));
+    let color = description(|| "Color".to_owned(), || Some("Color".to_owned()));
+    let request = description(|| "Request".to_owned(), || None);
+    assert_eq!(check_exported_names(&[&v1, &color, &request]), Ok(()));
+    assert_eq!(
+        check_exported_names(&[&v2, &request, &color, &v1]),
+        Err("v1__Method and v2__Method are both exported as M.Method".to_owned())
+    );
+}
+
+pub fn dependency_sorted_type_descriptions(dependencies: &Dependencies) -> Vec<&TypeDescription> {
     let mut remaining: std::collections::HashSet<_> = dependencies.keys().cloned().collect();
     let mut sorted_dependencies = Vec::new();
     while !remaining.is_empty() {
         let typ = {
+            let is_declared = |description: &TypeDescription, dependency| {
+                dependency == &description.typeid
+                    || !remaining.contains(dependency)
+                    || (description.is_indirect
+                        && !(dependencies[dependency].forward_declaration)().is_empty())
+            };
             let (typ, dependencies) = remaining
                 .iter()
-                .map(|typ| (typ, dependencies.get(typ).unwrap()))
-                .find(|&(_, dependencies)| dependencies.dependencies.is_disjoint(&remaining))
-                .unwrap();
+                .map(|typ| (typ, &dependencies[typ]))
+                .find(|&(_, description)| {
+                    description
+                        .dependencies
+                        .iter()
+                        .all(|dependency| is_declared(description, dependency))
+                })
+                .expect("Recursive types must refer to themselves through a pointer");
             sorted_dependencies.push(dependencies);
             *typ
         };
@@ -159,13 +250,28 @@ pub fn dependency_sorted_type_descriptions<'a>(
     sorted_dependencies
 }
 
+/// Names the payload struct of `variant` of the data-carrying enum `T`. The leading `__` is
+/// reserved, which keeps it apart from derived types, named after their module path.
+pub fn enum_payload_typename<T: Type + ?Sized>(variant: &str) -> String {
+    format!("__{}_{}", T::typename(), variant)
+}
+
+pub fn prefixed_enum_payload_typename<T: Type + ?Sized>(variant: &str) -> String {
+    prefixed(&enum_payload_typename::<T>(variant))
+}
+
+/// Forward declaration of types declared as `typedef struct X { ... } X;`.
+pub fn struct_type_forward_declaration<T: Type + ?Sized>() -> String {
+    format!("typedef struct {0} {0};", T::prefixed_c_typename())
+}
+
 pub fn ptr_type_metatype<T: Type + ?Sized>() -> String {
     format!(
         r#"
-local __typename_{self_typename} = ffi.metatype("{c_typename}", {{}})
-local __const_c_typename_{self_typename} = ffi.typeof("const {c_typename}[?]")
-local __c_function_argument_{self_typename} = ffi.typeof("{c_function_argument}[?]")
-local __c_mut_function_argument_{self_typename} = ffi.typeof("{c_mut_function_argument}[?]")
+__typename_{self_typename} = ffi.metatype("{c_typename}", {{}})
+__const_c_typename_{self_typename} = ffi.typeof("const {c_typename}[?]")
+__c_function_argument_{self_typename} = ffi.typeof("{c_function_argument}[?]")
+__c_mut_function_argument_{self_typename} = ffi.typeof("{c_mut_function_argument}[?]")
 "#,
         self_typename = T::typename(),
         c_typename = T::prefixed_c_typename(),
@@ -177,17 +283,131 @@ local __c_mut_function_argument_{self_typename} = ffi.typeof("{c_mut_function_ar
 pub fn primitive_type_metatype<T: Type + ?Sized>() -> String {
     format!(
         r#"
-local __const_c_typename_{self_typename} = ffi.typeof("const {c_typename}[?]")
-local __c_function_argument_{self_typename} = ffi.typeof("{c_function_argument}[?]")
-local __c_mut_function_argument_{self_typename} = ffi.typeof("{c_mut_function_argument}[?]")
+__const_c_typename_{self_typename} = ffi.typeof("const {c_typename}[?]")
+__c_function_argument_{self_typename} = ffi.typeof("{c_function_argument}[?]")
+__c_mut_function_argument_{self_typename} = ffi.typeof("{c_mut_function_argument}[?]")
 "#,
         self_typename = T::typename(),
-        c_typename = T::c_typename(),
+        c_typename = T::prefixed_c_typename(),
         c_function_argument = T::c_function_argument(),
         c_mut_function_argument = T::c_mut_function_argument()
     )
 }
 
+/// Transparent types share the C type of `U` and alias its Lua helpers.
+pub fn transparent_type_metatype<T: Type + ?Sized, U: Type + ?Sized>() -> String {
+    format!(
+        r#"
+__typename_{self_typename} = __typename_{typename}
+__const_c_typename_{self_typename} = __const_c_typename_{typename}
+__c_function_argument_{self_typename} = __c_function_argument_{typename}
+__c_mut_function_argument_{self_typename} = __c_mut_function_argument_{typename}
+"#,
+        self_typename = T::typename(),
+        typename = U::typename()
+    )
+}
+
+/// Fieldless enums are passed as their `i32` discriminant. `variants` holds the name and
+/// discriminant of every variant. Lua sees variant names unless `as_integer` is set.
+/// The variants are exported as `M.{name}`.
+pub fn enum_type_metatype<T: Type + ?Sized>(
+    name: &str,
+    variants: &[(&str, i32)],
+    as_integer: bool,
+) -> String {
+    let lua_value = |name: &str, discriminant: i32| {
+        if as_integer {
+            discriminant.to_string()
+        } else {
+            format!("{:?}", name)
+        }
+    };
+    format!(
+        r#"{primitive_type_metatype}__from_c_{self_typename} = {{
+{from_c}
+}}
+__into_c_{self_typename} = {{
+{into_c}
+}}
+M.{name} = {{
+{constants}
+}}
+"#,
+        primitive_type_metatype = primitive_type_metatype::<T>(),
+        self_typename = T::typename(),
+        name = name,
+        from_c = variants
+            .iter()
+            .map(|&(name, discriminant)| format!(
+                "    [{}] = {},",
+                discriminant,
+                lua_value(name, discriminant)
+            ))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        into_c = variants
+            .iter()
+            .flat_map(|&(name, discriminant)| vec![
+                format!("    {} = {},", name, discriminant),
+                format!("    [{}] = {},", discriminant, discriminant),
+            ])
+            .collect::<Vec<_>>()
+            .join("\n"),
+        constants = variants
+            .iter()
+            .map(|&(name, discriminant)| format!(
+                "    {} = {},",
+                name,
+                lua_value(name, discriminant)
+            ))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Conversions of types which may be recursive are defined once by their metatype, and
+/// referred to by name rather than inlined.
+pub fn named_conversions_metatype<T: Type + ?Sized>(from_c: &str, into_c: &str) -> String {
+    format!(
+        r#"__from_c_{self_typename} = {from_c}
+__into_c_{self_typename} = {into_c}
+"#,
+        self_typename = T::typename(),
+        from_c = from_c,
+        into_c = into_c
+    )
+}
+
+pub fn named_from_raw_function<T: Type + ?Sized>() -> String {
+    format!("__from_c_{}", T::typename())
+}
+
+pub fn named_into_raw_function<T: Type + ?Sized>() -> String {
+    format!("__into_c_{}", T::typename())
+}
+
+pub fn enum_type_from_raw_function<T: Type + ?Sized>() -> String {
+    format!(
+        "function(value) return __from_c_{typename}[value] end",
+        typename = T::typename()
+    )
+}
+
+pub fn enum_type_into_raw_function<T: Type + ?Sized>(name: &str) -> String {
+    format!(
+        r#"function(value)
+    local discriminant = __into_c_{typename}[value]
+    if discriminant == nil then
+        error("Invalid value for {name}: "..tostring(value))
+    end
+    return discriminant
+end"#,
+        typename = T::typename(),
+        name = name
+    )
+}
+
 pub fn ptr_type_create_pointer<T: IntoRawConversion>() -> String {
     T::function()
 }
@@ -223,6 +443,17 @@ end"#,
     )
 }
 
+pub fn immediate_type_create_pointer<T: IntoRawConversion>() -> String {
+    format!(
+        r#"function(value)
+    local f = {function}
+    return __const_c_typename_{typename}(1, {{ f(value) }})
+end"#,
+        function = T::function(),
+        typename = <T as Type>::typename()
+    )
+}
+
 fn primitive_type_create_pointer<T: IntoRawConversion>() -> String {
     format!(
         r#"function(value)
@@ -243,6 +474,8 @@ end"#,
 
 impl<T: Type + 'static> Type for Option<T> {
     const IS_PRIMITIVE: bool = false;
+    const IS_INDIRECT: bool = true;
+    const IS_NULLABLE: bool = true;
     fn typename() -> String {
         format!("Option_{}", T::typename())
     }
@@ -291,7 +524,7 @@ impl<T: IntoRawConversion + 'static> IntoRawConversion for Option<T> {
             r#"
 function(value)
     local f = {create_pointer}
-    if value ~= nil then
+    if value ~= nil and value ~= M.null then
         return __typename_{self_typename}(f(value))
     else
         return __typename_{self_typename}(nil)
@@ -306,35 +539,40 @@ end
         ptr_type_create_pointer::<Self>()
     }
     fn create_array() -> String {
-        panic!("Array of Option<T> are unreliable and have been disabled");
+        format!(
+            r#"function(value)
+    local result = {{}}
+    local f = {function}
+    local len = value.n or #value
+    for i = 1,len do
+        result[i] = f(value[i])
+    end
+    return __const_c_typename_{typename}(len, result)
+end"#,
+            function = <Self as IntoRawConversion>::function(),
+            typename = <Self as Type>::typename()
+        )
     }
 }
 
-impl<T: Type + 'static, E: Type + 'static> Type for Result<T, E> {
+impl<T: Type + 'static> Type for Box<T> {
     const IS_PRIMITIVE: bool = false;
+    const IS_INDIRECT: bool = true;
 
     fn typename() -> String {
-        format!(
-            "Result_{T_typename}_{E_typename}",
-            T_typename = T::typename(),
-            E_typename = E::typename()
-        )
+        format!("Box_{}", T::typename())
     }
     fn typedeclaration() -> String {
         format!(
             r#"typedef struct {{
-    const {T_c_typename} *ok;
-    const {E_c_typename} *err;
+    const {c_typename} *ptr;
 }} {self_typename};"#,
-            T_c_typename = T::prefixed_c_typename(),
-            E_c_typename = E::prefixed_c_typename(),
+            c_typename = <T as Type>::prefixed_c_typename(),
             self_typename = Self::prefixed_typename()
         )
     }
     fn dependencies() -> Dependencies {
-        let mut dependencies = make_dependencies::<T>();
-        dependencies.extend(make_dependencies::<E>());
-        dependencies
+        make_dependencies::<T>()
     }
     fn c_function_argument() -> String {
         format!("const {}*", Self::prefixed_c_typename())
@@ -344,22 +582,14 @@ impl<T: Type + 'static, E: Type + 'static> Type for Result<T, E> {
     }
 }
 
-impl<T: FromRawConversion + 'static, E: FromRawConversion + 'static> FromRawConversion
-    for Result<T, E>
-{
+impl<T: FromRawConversion + 'static> FromRawConversion for Box<T> {
     fn function() -> String {
         format!(
             r#"function(value)
-    if value.ok ~= nil then
-        local f = {T_function}
-        return f(value.ok[0])
-    else
-        local f = {E_function}
-        return nil, f(value.err[0])
-    end
+    local f = {function}
+    return f(value.ptr[0])
 end"#,
-            T_function = T::function(),
-            E_function = E::function()
+            function = T::function()
         )
     }
     fn gc() -> bool {
@@ -367,47 +597,97 @@ end"#,
     }
 }
 
-impl<T: Type + 'static> Type for Vec<T> {
+impl<T: IntoRawConversion + 'static> IntoRawConversion for Box<T> {
+    fn function() -> String {
+        format!(
+            r#"
+function(value)
+    local f = {create_pointer}
+    return __typename_{self_typename}(f(value))
+end
+"#,
+            self_typename = <Self as Type>::typename(),
+            create_pointer = <T as IntoRawConversion>::create_pointer()
+        )
+    }
+    fn create_pointer() -> String {
+        ptr_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        immediate_type_create_array::<Self>()
+    }
+}
+
+impl<T: Type + 'static, E: Type + 'static> Type for Result<T, E> {
     const IS_PRIMITIVE: bool = false;
+    const IS_INDIRECT: bool = true;
 
     fn typename() -> String {
-        format!("Vec_{}", T::typename())
+        format!(
+            "Result_{T_typename}_{E_typename}",
+            T_typename = T::typename(),
+            E_typename = E::typename()
+        )
     }
     fn typedeclaration() -> String {
         format!(
             r#"typedef struct {{
-    const {c_typename} *ptr;
-    size_t len;
-    size_t capacity;
+    const {T_c_typename} *ok;
+    const {E_c_typename} *err;
 }} {self_typename};"#,
-            c_typename = <T as Type>::prefixed_c_typename(),
+            T_c_typename = T::prefixed_c_typename(),
+            E_c_typename = E::prefixed_c_typename(),
             self_typename = Self::prefixed_typename()
         )
     }
     fn dependencies() -> Dependencies {
-        make_dependencies::<T>()
+        let mut dependencies = make_dependencies::<T>();
+        dependencies.extend(make_dependencies::<E>());
+        dependencies
     }
     fn c_function_argument() -> String {
-        format!("const {}*", <Self as Type>::prefixed_c_typename())
+        format!("const {}*", Self::prefixed_c_typename())
     }
     fn c_mut_function_argument() -> String {
-        format!("{}*", <Self as Type>::prefixed_c_typename())
+        format!("{}*", Self::prefixed_c_typename())
     }
 }
 
-impl<T: FromRawConversion + 'static> FromRawConversion for Vec<T> {
+impl<T: FromRawConversion + 'static, E: FromRawConversion + 'static> FromRawConversion
+    for Result<T, E>
+{
     fn function() -> String {
         format!(
             r#"function(value)
-    local ret = {{}}
-    local len = tonumber(value.len)
-    local f = {function}
-    for i = 1,len do
-        ret[i] = f(value.ptr[i - 1])
+    if value.ok ~= nil then
+        local f = {T_function}
+        return {{ ok = f(value.ok[0]) }}
+    else
+        local f = {E_function}
+        local err = f(value.err[0])
+        if err == nil then
+            err = M.null
+        end
+        return {{ err = err }}
     end
-    return ret
 end"#,
-            function = T::function()
+            T_function = T::function(),
+            E_function = E::function()
+        )
+    }
+    fn return_function() -> String {
+        format!(
+            r#"function(value)
+    if value.ok ~= nil then
+        local f = {T_function}
+        return f(value.ok[0])
+    else
+        local f = {E_function}
+        return nil, f(value.err[0])
+    end
+end"#,
+            T_function = T::function(),
+            E_function = E::function()
         )
     }
     fn gc() -> bool {
@@ -415,21 +695,31 @@ end"#,
     }
 }
 
-impl<T: IntoRawConversion + 'static> IntoRawConversion for Vec<T> {
+impl<T: IntoRawConversion + 'static, E: IntoRawConversion + 'static> IntoRawConversion
+    for Result<T, E>
+{
     fn function() -> String {
+        // Takes `{ ok = value }` or `{ err = err }`, as well as the `value, err` pair returned by
+        // functions when packed into a table.
         format!(
             r#"
 function(value)
-    if type(value) == "string" then
-        return __typename_{self_typename}(value, #value)
+    local ok, err = value.ok, value.err
+    if ok == nil and err == nil then
+        ok, err = value[1], value[2]
+    end
+    if err ~= nil then
+        local f = {E_create_pointer}
+        return __typename_{self_typename}(nil, f(err))
     else
-        local f = {create_array}
-        return __typename_{self_typename}(f(value), #value, 0)
+        local f = {T_create_pointer}
+        return __typename_{self_typename}(f(ok), nil)
     end
 end
 "#,
             self_typename = <Self as Type>::typename(),
-            create_array = <T as IntoRawConversion>::create_array()
+            T_create_pointer = T::create_pointer(),
+            E_create_pointer = E::create_pointer()
         )
     }
     fn create_pointer() -> String {
@@ -440,74 +730,1128 @@ end
     }
 }
 
-impl Type for String {
-    const IS_PRIMITIVE: bool = true;
-
-    fn c_typename() -> String {
-        "char *".to_owned()
-    }
-    fn typename() -> String {
-        "__string_ptr".to_owned()
-    }
-    fn c_function_argument() -> String {
-        format!("const {}", Self::c_typename())
-    }
-    fn c_mut_function_argument() -> String {
-        <Self as Type>::c_typename()
-    }
-}
-
-impl FromRawConversion for String {
-    fn function() -> String {
-        "ffi.string".to_owned()
-    }
-    fn gc() -> bool {
-        true
-    }
-}
-
-impl IntoRawConversion for String {
-    fn function() -> String {
-        "function(value) return value end".to_owned()
-    }
-    fn create_pointer() -> String {
-        primitive_type_create_pointer::<Self>()
-    }
-    fn create_array() -> String {
-        primitive_type_create_array::<Self>()
-    }
-}
-
-macro_rules! primitive_lua_from_native {
-    ($($typ:ty)*) => {
+macro_rules! tuple_lua_native {
+    ($($arity:tt { $($index:tt $field:ident $typ:ident)* })*) => {
         $(
-            impl Type for $typ {
-                const IS_PRIMITIVE: bool = true;
+            impl<$($typ: Type + 'static),*> Type for ($($typ,)*) {
+                const IS_PRIMITIVE: bool = false;
 
                 fn typename() -> String {
-                    stringify!($typ).to_owned()
+                    let typenames: &[String] = &[$($typ::typename()),*];
+                    format!("Tuple{}_{}", $arity, typenames.join("_"))
                 }
-                fn c_typename() -> String {
-                    stringify!($typ).to_owned()
+                fn typedeclaration() -> String {
+                    let fields: &[String] = &[
+                        $(format!("    const {} {};", $typ::prefixed_c_typename(), stringify!($field))),*
+                    ];
+                    format!(
+                        r#"typedef struct {self_typename} {{
+{fields}
+}} {self_typename};"#,
+                        fields = fields.join("\n"),
+                        self_typename = Self::prefixed_typename()
+                    )
+                }
+                fn forward_declaration() -> String {
+                    struct_type_forward_declaration::<Self>()
+                }
+                fn dependencies() -> Dependencies {
+                    let mut dependencies = Dependencies::new();
+                    $(dependencies.extend(make_dependencies::<$typ>());)*
+                    dependencies
                 }
                 fn c_function_argument() -> String {
-                    <Self as Type>::c_typename()
+                    format!("const {}*", Self::prefixed_c_typename())
                 }
                 fn c_mut_function_argument() -> String {
-                    <Self as Type>::c_typename()
+                    format!("{}*", Self::prefixed_c_typename())
                 }
             }
 
-            impl FromRawConversion for $typ {
+            impl<$($typ: FromRawConversion + 'static),*> FromRawConversion for ($($typ,)*) {
                 fn function() -> String {
-                    "function(value) return value end".to_owned()
+                    let values: &[String] = &[
+                        $(format!("({})(value.{})", $typ::function(), stringify!($field))),*
+                    ];
+                    format!(
+                        r#"function(value)
+    return {{ {values} }}
+end"#,
+                        values = values.join(", ")
+                    )
+                }
+                fn return_function() -> String {
+                    let values: &[String] = &[
+                        $(format!("({})(value.{})", $typ::function(), stringify!($field))),*
+                    ];
+                    format!(
+                        r#"function(value)
+    return {values}
+end"#,
+                        values = values.join(",\n        ")
+                    )
                 }
                 fn gc() -> bool {
-                    false
+                    true
                 }
             }
 
-            impl IntoRawConversion for $typ {
+            impl<$($typ: IntoRawConversion + 'static),*> IntoRawConversion for ($($typ,)*) {
+                fn function() -> String {
+                    let values: &[String] = &[
+                        $(format!("({})(value[{}])", $typ::function(), $index + 1)),*
+                    ];
+                    format!(
+                        r#"function(value)
+    return __typename_{self_typename}(
+        {values})
+end"#,
+                        self_typename = <Self as Type>::typename(),
+                        values = values.join(",\n        ")
+                    )
+                }
+                fn create_pointer() -> String {
+                    ptr_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+tuple_lua_native!(
+    2 { 0 _0 T0 1 _1 T1 }
+    3 { 0 _0 T0 1 _1 T1 2 _2 T2 }
+    4 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 }
+    5 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 }
+    6 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 }
+    7 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 6 _6 T6 }
+    8 { 0 _0 T0 1 _1 T1 2 _2 T2 3 _3 T3 4 _4 T4 5 _5 T5 6 _6 T6 7 _7 T7 }
+);
+
+// Callbacks are Lua functions, cast to C callbacks for the duration of the call. Errors
+// raised by them are caught and returned to Rust with the status.
+macro_rules! callback_lua_native {
+    ($(($($arg:ident $typ:ident)*))*) => {
+        $(
+            impl<$($typ: Type + 'static,)* R: Type + 'static> Type for fn($($typ),*) -> R {
+                fn typename() -> String {
+                    let typenames: &[String] = &[$($typ::typename(),)* R::typename()];
+                    format!("Callback_{}", typenames.join("_"))
+                }
+                // Casting to a C type given as a string declares a new function type each
+                // time, and LuaJIT runs out of C types after a few thousand calls.
+                fn metatype() -> String {
+                    format!(
+                        "\n__typename_{} = ffi.typeof(\"{}\")\n",
+                        Self::typename(),
+                        Self::c_function_argument()
+                    )
+                }
+                fn dependencies() -> Dependencies {
+                    let mut dependencies = Dependencies::new();
+                    $(dependencies.extend(make_dependencies::<$typ>());)*
+                    dependencies.extend(make_dependencies::<R>());
+                    dependencies.extend(make_dependencies::<String>());
+                    dependencies
+                }
+                fn c_function_argument() -> String {
+                    let arguments: &[String] = &[
+                        $($typ::c_mut_function_argument(),)*
+                        format!("{}*", R::c_function_argument()),
+                        format!("{}*", String::c_function_argument()),
+                    ];
+                    format!("int32_t (*)({})", arguments.join(", "))
+                }
+                fn c_mut_function_argument() -> String {
+                    Self::c_function_argument()
+                }
+            }
+
+            impl<$($typ: FromRawConversion + 'static,)* R: IntoRawConversion + 'static>
+                IntoRawConversion for fn($($typ),*) -> R
+            {
+                // Errors raised by the Lua function are kept in `__errors.last`, so that the
+                // generated function can raise them again.
+                fn function() -> String {
+                    let conversions: &[String] = &[
+                        $(format!(
+                            "local __from_c_{} = {}",
+                            stringify!($arg),
+                            $typ::function()
+                        ),)*
+                    ];
+                    let parameters: &[&str] = &[$(stringify!($arg)),*];
+                    let arguments: &[String] = &[
+                        $(format!("__from_c_{0}({0})", stringify!($arg)),)*
+                    ];
+                    format!(
+                        r#"function(value, __errors)
+    {conversions}
+    local __into_c = {into_c}
+    local __into_c_err = {into_c_err}
+    return ffi.cast(__typename_{typename}, function({parameters}__ret, __err)
+        local ok, result = pcall(function()
+            return __into_c(value({arguments}))
+        end)
+        if not ok then
+            __errors.last = result
+            __err[0] = __into_c_err(tostring(result))
+            return 1
+        end
+        __ret[0] = result
+        return 0
+    end)
+end"#,
+                        conversions = conversions.join("\n    "),
+                        into_c = R::function(),
+                        into_c_err = <String as IntoRawConversion>::function(),
+                        typename = Self::typename(),
+                        parameters = parameters
+                            .iter()
+                            .map(|parameter| format!("{}, ", parameter))
+                            .collect::<String>(),
+                        arguments = arguments.join(", ")
+                    )
+                }
+                fn create_pointer() -> String {
+                    panic!("Callbacks can only be function arguments")
+                }
+                fn create_array() -> String {
+                    panic!("Callbacks can only be function arguments")
+                }
+            }
+        )*
+    };
+}
+
+callback_lua_native!(
+    ()
+    (a0 A0)
+    (a0 A0 a1 A1)
+    (a0 A0 a1 A1 a2 A2)
+    (a0 A0 a1 A1 a2 A2 a3 A3)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6 a7 A7)
+);
+
+// Closures are pointers to a `CClosure`, which Lua calls through its `call` function and drops
+// through its `drop` function once it collects them.
+macro_rules! closure_lua_native {
+    ($(($($arg:ident $typ:ident)*))*) => {
+        $(
+            impl<$($typ: IntoRawConversion + 'static,)* R: FromRawConversion + 'static> Type
+                for c_marshalling::Closure<fn($($typ),*) -> R>
+            where
+                fn($($typ),*) -> R: c_marshalling::Signature,
+            {
+                fn typename() -> String {
+                    let typenames: &[String] = &[$($typ::typename(),)* R::typename()];
+                    format!("Closure_{}", typenames.join("_"))
+                }
+                fn c_typename() -> String {
+                    format!("{} *", Self::typename())
+                }
+                fn typedeclaration() -> String {
+                    let arguments: &[String] = &[
+                        format!("const struct {} *", Self::prefixed_typename()),
+                        $($typ::c_function_argument(),)*
+                        format!("{}*", R::c_mut_function_argument()),
+                    ];
+                    format!(
+                        r#"typedef struct {self_typename} {{
+    int32_t (*call)({arguments});
+    int32_t (*free)({output});
+    void (*drop)(const struct {self_typename} *);
+}} {self_typename};"#,
+                        self_typename = Self::prefixed_typename(),
+                        arguments = arguments.join(", "),
+                        output = R::c_mut_function_argument()
+                    )
+                }
+                fn forward_declaration() -> String {
+                    format!("typedef struct {0} {0};", Self::prefixed_typename())
+                }
+                fn metatype() -> String {
+                    let parameters: &[&str] = &[$(stringify!($arg)),*];
+                    let arguments: &[String] = &[
+                        $(format!("({})({})", $typ::function(), stringify!($arg)),)*
+                    ];
+                    format!(
+                        r#"{primitive_metatype}
+ffi.metatype("{self_typename}", {{
+    __call = function(self{parameters})
+        local __ret_ptr = __c_mut_function_argument_{typename}(1, {{}})
+        local status = self.call(
+            self,
+            {arguments}__ret_ptr)
+        if status ~= 0 then
+            error("Closure failed with status "..status)
+        end
+        local __ret = __ret_ptr[0]
+        {gc}
+        local f = {function}
+        return f(__ret)
+    end
+}})
+"#,
+                        primitive_metatype = primitive_type_metatype::<Self>(),
+                        self_typename = Self::prefixed_typename(),
+                        parameters = parameters
+                            .iter()
+                            .map(|parameter| format!(", {}", parameter))
+                            .collect::<String>(),
+                        typename = R::typename(),
+                        arguments = arguments
+                            .iter()
+                            .map(|argument| format!("{},\n            ", argument))
+                            .collect::<String>(),
+                        gc = if R::gc() { "ffi.gc(__ret, self.free)" } else { "" },
+                        function = R::return_function()
+                    )
+                }
+                fn dependencies() -> Dependencies {
+                    let mut dependencies = Dependencies::new();
+                    $(dependencies.extend(make_dependencies::<$typ>());)*
+                    dependencies.extend(make_dependencies::<R>());
+                    dependencies
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}", Self::prefixed_c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    Self::prefixed_c_typename()
+                }
+            }
+
+            impl<$($typ: IntoRawConversion + 'static,)* R: FromRawConversion + 'static>
+                FromRawConversion for c_marshalling::Closure<fn($($typ),*) -> R>
+            where
+                fn($($typ),*) -> R: c_marshalling::Signature,
+            {
+                fn function() -> String {
+                    "function(value) return ffi.gc(value, value.drop) end".to_owned()
+                }
+                fn gc() -> bool {
+                    false
+                }
+            }
+        )*
+    };
+}
+
+closure_lua_native!(
+    ()
+    (a0 A0)
+    (a0 A0 a1 A1)
+    (a0 A0 a1 A1 a2 A2)
+    (a0 A0 a1 A1 a2 A2 a3 A3)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6)
+    (a0 A0 a1 A1 a2 A2 a3 A3 a4 A4 a5 A5 a6 A6 a7 A7)
+);
+
+// Iterator items share the C type of `Option<T>`. Items which would be `nil` are `M.null`
+// instead, since `nil` ends a Lua loop.
+impl<T: Type + 'static> Type for c_marshalling::IteratorItem<T> {
+    fn typename() -> String {
+        format!("IteratorItem_{}", T::typename())
+    }
+    fn c_typename() -> String {
+        <Option<T> as Type>::c_typename()
+    }
+    fn prefixed_c_typename() -> String {
+        <Option<T> as Type>::prefixed_c_typename()
+    }
+    fn forward_declaration() -> String {
+        <Option<T> as Type>::forward_declaration()
+    }
+    fn metatype() -> String {
+        transparent_type_metatype::<Self, Option<T>>()
+    }
+    fn dependencies() -> Dependencies {
+        make_dependencies::<Option<T>>()
+    }
+    fn c_function_argument() -> String {
+        <Option<T> as Type>::c_function_argument()
+    }
+    fn c_mut_function_argument() -> String {
+        <Option<T> as Type>::c_mut_function_argument()
+    }
+}
+
+impl<T: FromRawConversion + 'static> FromRawConversion for c_marshalling::IteratorItem<T> {
+    fn function() -> String {
+        format!(
+            r#"function(value)
+    if value.ptr ~= nil then
+        local f = {function}
+        local item = f(value.ptr[0])
+        if item == nil then
+            return M.null
+        end
+        return item
+    else
+        return nil
+    end
+end"#,
+            function = T::function()
+        )
+    }
+    fn gc() -> bool {
+        true
+    }
+}
+
+impl<T: Type + 'static> Type for Vec<T> {
+    const IS_PRIMITIVE: bool = false;
+    const IS_INDIRECT: bool = true;
+
+    fn typename() -> String {
+        format!("Vec_{}", T::typename())
+    }
+    fn typedeclaration() -> String {
+        format!(
+            r#"typedef struct {{
+    const {c_typename} *ptr;
+    size_t len;
+    size_t capacity;
+}} {self_typename};"#,
+            c_typename = <T as Type>::prefixed_c_typename(),
+            self_typename = Self::prefixed_typename()
+        )
+    }
+    fn dependencies() -> Dependencies {
+        make_dependencies::<T>()
+    }
+    fn c_function_argument() -> String {
+        format!("const {}*", <Self as Type>::prefixed_c_typename())
+    }
+    fn c_mut_function_argument() -> String {
+        format!("{}*", <Self as Type>::prefixed_c_typename())
+    }
+}
+
+impl<T: FromRawConversion + 'static> FromRawConversion for Vec<T> {
+    fn function() -> String {
+        format!(
+            r#"function(value)
+    local ret = {{}}
+    local len = tonumber(value.len)
+    local f = {function}
+    for i = 1,len do
+        ret[i] = f(value.ptr[i - 1])
+    end{n}
+    return ret
+end"#,
+            function = T::function(),
+            n = if T::IS_NULLABLE {
+                "\n    ret.n = len"
+            } else {
+                ""
+            }
+        )
+    }
+    fn gc() -> bool {
+        true
+    }
+}
+
+impl<T: IntoRawConversion + 'static> IntoRawConversion for Vec<T> {
+    fn function() -> String {
+        format!(
+            r#"
+function(value)
+    if type(value) == "string" then
+        return __typename_{self_typename}(value, #value)
+    else
+        local f = {create_array}
+        return __typename_{self_typename}(f(value), {len}, 0)
+    end
+end
+"#,
+            self_typename = <Self as Type>::typename(),
+            create_array = <T as IntoRawConversion>::create_array(),
+            len = if T::IS_NULLABLE {
+                "value.n or #value"
+            } else {
+                "#value"
+            }
+        )
+    }
+    fn create_pointer() -> String {
+        ptr_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        immediate_type_create_array::<Self>()
+    }
+}
+
+impl<T: Type + 'static, const N: usize> Type for [T; N] {
+    const IS_PRIMITIVE: bool = false;
+
+    fn typename() -> String {
+        format!("Array_{}_{}", T::typename(), N)
+    }
+    fn typedeclaration() -> String {
+        format!(
+            r#"typedef struct {{
+    {c_typename} v[{len}];
+}} {self_typename};"#,
+            c_typename = <T as Type>::prefixed_c_typename(),
+            len = N,
+            self_typename = Self::prefixed_typename()
+        )
+    }
+    fn dependencies() -> Dependencies {
+        make_dependencies::<T>()
+    }
+    fn c_function_argument() -> String {
+        format!("const {}*", <Self as Type>::prefixed_c_typename())
+    }
+    fn c_mut_function_argument() -> String {
+        format!("{}*", <Self as Type>::prefixed_c_typename())
+    }
+}
+
+impl<T: FromRawConversion + 'static, const N: usize> FromRawConversion for [T; N] {
+    fn function() -> String {
+        format!(
+            r#"function(value)
+    local ret = {{}}
+    local f = {function}
+    for i = 1,{len} do
+        ret[i] = f(value.v[i - 1])
+    end{n}
+    return ret
+end"#,
+            function = T::function(),
+            len = N,
+            n = if T::IS_NULLABLE {
+                format!("\n    ret.n = {}", N)
+            } else {
+                "".to_owned()
+            }
+        )
+    }
+    fn gc() -> bool {
+        true
+    }
+}
+
+impl<T: IntoRawConversion + 'static, const N: usize> IntoRawConversion for [T; N] {
+    fn function() -> String {
+        format!(
+            r#"function(value)
+    local len = {len}
+    if len ~= {expected_len} then
+        error("Invalid length for {self_typename}: "..tostring(len))
+    end
+    local f = {create_array}
+    local ret = __typename_{self_typename}()
+    ffi.copy(ret.v, f(value), ffi.sizeof(ret.v))
+    return ret
+end"#,
+            self_typename = <Self as Type>::typename(),
+            create_array = <T as IntoRawConversion>::create_array(),
+            expected_len = N,
+            len = if T::IS_NULLABLE {
+                "value.n or #value"
+            } else {
+                "#value"
+            }
+        )
+    }
+    fn create_pointer() -> String {
+        ptr_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        immediate_type_create_array::<Self>()
+    }
+}
+
+macro_rules! set_lua_native {
+    ($($set:ident)*) => {
+        $(
+            impl<T: Type + 'static> Type for std::collections::$set<T> {
+                const IS_PRIMITIVE: bool = false;
+                const IS_INDIRECT: bool = true;
+
+                fn typename() -> String {
+                    format!("{}_{}", stringify!($set), T::typename())
+                }
+                fn typedeclaration() -> String {
+                    format!(
+                        r#"typedef struct {{
+    const {c_typename} *ptr;
+    size_t len;
+    size_t capacity;
+}} {self_typename};"#,
+                        c_typename = <T as Type>::prefixed_c_typename(),
+                        self_typename = Self::prefixed_typename()
+                    )
+                }
+                fn dependencies() -> Dependencies {
+                    make_dependencies::<T>()
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}*", Self::prefixed_c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    format!("{}*", Self::prefixed_c_typename())
+                }
+            }
+
+            impl<T: FromRawConversion + 'static> FromRawConversion for std::collections::$set<T> {
+                fn function() -> String {
+                    // See the map conversion for why cdata elements are converted to numbers.
+                    format!(
+                        r#"function(value)
+    local ret = {{}}
+    local len = tonumber(value.len)
+    local f = {function}
+    for i = 1,len do
+        local element = f(value.ptr[i - 1])
+        if type(element) == "cdata" then
+            local number = tonumber(element)
+            if number > -2^53 and number < 2^53 then
+                element = number
+            end
+        end
+        ret[element] = true
+    end
+    return ret
+end"#,
+                        function = T::function()
+                    )
+                }
+                fn gc() -> bool {
+                    true
+                }
+            }
+
+            impl<T: IntoRawConversion + 'static> IntoRawConversion for std::collections::$set<T> {
+                fn function() -> String {
+                    // Accepts both set-style `{ [element] = true }` and array-style tables. A
+                    // table is array-style if it is a sequence, unless all its values are
+                    // `true`, as in the set `{ [1] = true, [2] = true }` returned for a
+                    // `HashSet<i32>`. Keys of set-style tables whose value is `false` are not
+                    // elements.
+                    format!(
+                        r#"
+function(value)
+    local length = #value
+    local is_array = false
+    if length > 0 then
+        local count = 0
+        for _, element in pairs(value) do
+            count = count + 1
+            is_array = is_array or element ~= true
+        end
+        is_array = is_array and count == length
+    end
+    local elements = {{}}
+    if is_array then
+        for i = 1, length do
+            elements[i] = value[i]
+        end
+    else
+        for key, element in pairs(value) do
+            if element then
+                elements[#elements + 1] = key
+            end
+        end
+    end
+    local f = {create_array}
+    return __typename_{self_typename}(f(elements), #elements, 0)
+end
+"#,
+                        self_typename = <Self as Type>::typename(),
+                        create_array = <T as IntoRawConversion>::create_array()
+                    )
+                }
+                fn create_pointer() -> String {
+                    ptr_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+set_lua_native!(HashSet BTreeSet);
+
+macro_rules! map_lua_native {
+    ($($map:ident)*) => {
+        $(
+            impl<K: Type + 'static, V: Type + 'static> Type for std::collections::$map<K, V> {
+                const IS_PRIMITIVE: bool = false;
+                const IS_INDIRECT: bool = true;
+
+                fn typename() -> String {
+                    format!("{}_{}_{}", stringify!($map), K::typename(), V::typename())
+                }
+                fn typedeclaration() -> String {
+                    format!(
+                        r#"typedef struct {{
+    const {K_c_typename} *keys;
+    const {V_c_typename} *values;
+    size_t len;
+}} {self_typename};"#,
+                        K_c_typename = K::prefixed_c_typename(),
+                        V_c_typename = V::prefixed_c_typename(),
+                        self_typename = Self::prefixed_typename()
+                    )
+                }
+                fn dependencies() -> Dependencies {
+                    let mut dependencies = make_dependencies::<K>();
+                    dependencies.extend(make_dependencies::<V>());
+                    dependencies
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}*", Self::prefixed_c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    format!("{}*", Self::prefixed_c_typename())
+                }
+            }
+
+            impl<K: FromRawConversion + 'static, V: FromRawConversion + 'static> FromRawConversion
+                for std::collections::$map<K, V>
+            {
+                fn function() -> String {
+                    // 64-bit integers are cdata, which Lua compares by identity when used as
+                    // table keys, so such keys are converted to numbers. Keys beyond 2^53,
+                    // which numbers cannot represent exactly, stay cdata so that distinct keys
+                    // do not collide.
+                    format!(
+                        r#"function(value)
+    local ret = {{}}
+    local len = tonumber(value.len)
+    local key_f = {K_function}
+    local value_f = {V_function}
+    for i = 0,len - 1 do
+        local key = key_f(value.keys[i])
+        if type(key) == "cdata" then
+            local number = tonumber(key)
+            if number > -2^53 and number < 2^53 then
+                key = number
+            end
+        end
+        ret[key] = value_f(value.values[i])
+    end
+    return ret
+end"#,
+                        K_function = K::function(),
+                        V_function = V::function()
+                    )
+                }
+                fn gc() -> bool {
+                    true
+                }
+            }
+
+            impl<K: IntoRawConversion + 'static, V: IntoRawConversion + 'static> IntoRawConversion
+                for std::collections::$map<K, V>
+            {
+                fn function() -> String {
+                    format!(
+                        r#"
+function(value)
+    local keys = {{}}
+    local values = {{}}
+    for key, value in pairs(value) do
+        keys[#keys + 1] = key
+        values[#values + 1] = value
+    end
+    local key_f = {K_create_array}
+    local value_f = {V_create_array}
+    return __typename_{self_typename}(key_f(keys), value_f(values), #keys)
+end
+"#,
+                        self_typename = <Self as Type>::typename(),
+                        K_create_array = K::create_array(),
+                        V_create_array = V::create_array()
+                    )
+                }
+                fn create_pointer() -> String {
+                    ptr_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+map_lua_native!(HashMap BTreeMap);
+
+/// Declares a string type, which is passed with its length so that it may contain zero-bytes.
+fn bytes_type_typedeclaration<T: Type + ?Sized>() -> String {
+    format!(
+        r#"typedef struct {{
+    const char *ptr;
+    size_t len;
+}} {self_typename};"#,
+        self_typename = T::prefixed_typename()
+    )
+}
+
+fn bytes_type_into_raw_function<T: Type + ?Sized>() -> String {
+    format!(
+        "function(value) return __typename_{self_typename}(value, #value) end",
+        self_typename = T::typename()
+    )
+}
+
+// Owned strings and byte buffers are Lua strings.
+macro_rules! bytes_lua_native {
+    ($($typ:ty => $typename:expr),*) => {
+        $(
+            impl Type for $typ {
+                fn typename() -> String {
+                    $typename.to_owned()
+                }
+                fn typedeclaration() -> String {
+                    bytes_type_typedeclaration::<Self>()
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}*", Self::prefixed_c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    format!("{}*", Self::prefixed_c_typename())
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                fn function() -> String {
+                    "function(value) return ffi.string(value.ptr, value.len) end".to_owned()
+                }
+                fn gc() -> bool {
+                    true
+                }
+            }
+
+            impl IntoRawConversion for $typ {
+                fn function() -> String {
+                    bytes_type_into_raw_function::<Self>()
+                }
+                fn create_pointer() -> String {
+                    ptr_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+
+            impl SliceElement for $typ {}
+        )*
+    };
+}
+
+bytes_lua_native!(
+    String => "String",
+    c_marshalling::Bytes => "Bytes",
+    std::ffi::OsString => "OsString",
+    std::path::PathBuf => "PathBuf"
+);
+
+// Other string types share the C type of `String` and alias its Lua helpers.
+macro_rules! string_like_lua_native {
+    ($($typ:ty => $typename:expr),*) => {
+        $(
+            impl Type for $typ {
+                fn typename() -> String {
+                    $typename.to_owned()
+                }
+                fn c_typename() -> String {
+                    <String as Type>::c_typename()
+                }
+                fn prefixed_c_typename() -> String {
+                    <String as Type>::prefixed_c_typename()
+                }
+                fn metatype() -> String {
+                    transparent_type_metatype::<Self, String>()
+                }
+                fn dependencies() -> Dependencies {
+                    make_dependencies::<String>()
+                }
+                fn c_function_argument() -> String {
+                    <String as Type>::c_function_argument()
+                }
+                fn c_mut_function_argument() -> String {
+                    <String as Type>::c_mut_function_argument()
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                fn function() -> String {
+                    <String as FromRawConversion>::function()
+                }
+                fn gc() -> bool {
+                    <String as FromRawConversion>::gc()
+                }
+            }
+
+            impl IntoRawConversion for $typ {
+                fn function() -> String {
+                    <String as IntoRawConversion>::function()
+                }
+                fn create_pointer() -> String {
+                    <String as IntoRawConversion>::create_pointer()
+                }
+                fn create_array() -> String {
+                    <String as IntoRawConversion>::create_array()
+                }
+            }
+
+            impl SliceElement for $typ {}
+        )*
+    };
+}
+
+string_like_lua_native!(
+    Box<str> => "BoxStr",
+    std::borrow::Cow<'static, str> => "CowStr",
+    std::rc::Rc<str> => "RcStr",
+    std::sync::Arc<str> => "ArcStr"
+);
+
+// Borrowed strings are only supported as arguments.
+macro_rules! bytes_ref_lua_native {
+    ($($typ:ty => $typename:expr),*) => {
+        $(
+            impl Type for $typ {
+                fn typename() -> String {
+                    $typename.to_owned()
+                }
+                fn typedeclaration() -> String {
+                    bytes_type_typedeclaration::<Self>()
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}*", Self::prefixed_c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    // Mutable not supported
+                    Self::c_function_argument()
+                }
+            }
+
+            impl IntoRawConversion for $typ {
+                fn function() -> String {
+                    bytes_type_into_raw_function::<Self>()
+                }
+                fn create_pointer() -> String {
+                    ptr_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+
+            impl SliceElement for $typ {}
+        )*
+    };
+}
+
+bytes_ref_lua_native!(
+    &str => "str",
+    &std::ffi::OsStr => "OsStr",
+    &std::path::Path => "Path"
+);
+
+impl Type for std::ffi::CString {
+    const IS_PRIMITIVE: bool = true;
+
+    fn c_typename() -> String {
+        "char *".to_owned()
+    }
+    fn typename() -> String {
+        "__string_ptr".to_owned()
+    }
+    fn c_function_argument() -> String {
+        format!("const {}", Self::c_typename())
+    }
+    fn c_mut_function_argument() -> String {
+        <Self as Type>::c_typename()
+    }
+}
+
+impl FromRawConversion for std::ffi::CString {
+    fn function() -> String {
+        "ffi.string".to_owned()
+    }
+    fn gc() -> bool {
+        true
+    }
+}
+
+impl IntoRawConversion for std::ffi::CString {
+    fn function() -> String {
+        "function(value) return value end".to_owned()
+    }
+    fn create_pointer() -> String {
+        primitive_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        primitive_type_create_array::<Self>()
+    }
+}
+
+// Handles are pointers to a `CHandle`, which Lua drops through its own `drop` function once
+// it collects them.
+impl<T: 'static> Type for c_marshalling::Handle<T> {
+    fn typename() -> String {
+        let name: String = std::any::type_name::<T>()
+            .replace("::", "__")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("Handle_{}", name)
+    }
+    fn c_typename() -> String {
+        format!("{} *", Self::typename())
+    }
+    fn typedeclaration() -> String {
+        format!(
+            "typedef struct {0} {{ void *ptr; void (*drop)(const struct {0} *); intptr_t __borrows; }} {0};",
+            Self::prefixed_typename()
+        )
+    }
+    fn forward_declaration() -> String {
+        format!("typedef struct {0} {0};", Self::prefixed_typename())
+    }
+    fn metatype() -> String {
+        primitive_type_metatype::<Self>()
+    }
+    fn c_function_argument() -> String {
+        format!("const {}", Self::prefixed_c_typename())
+    }
+    fn c_mut_function_argument() -> String {
+        Self::prefixed_c_typename()
+    }
+}
+
+impl<T: 'static> FromRawConversion for c_marshalling::Handle<T> {
+    fn function() -> String {
+        "function(value) return ffi.gc(value, value.drop) end".to_owned()
+    }
+    fn gc() -> bool {
+        false
+    }
+}
+
+impl<T: 'static> IntoRawConversion for c_marshalling::Handle<T> {
+    fn function() -> String {
+        "function(value) return value end".to_owned()
+    }
+    fn create_pointer() -> String {
+        primitive_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        primitive_type_create_array::<Self>()
+    }
+}
+
+/// Receivers of methods, `&self` and `&mut self`, which are passed as `const void *`. Like
+/// `c_marshalling::Receiver`, it resolves to `ValueReceiver` for types with value marshalling
+/// and to `HandleReceiver` for any other type.
+pub struct Receiver<T>(std::marker::PhantomData<T>);
+
+impl<T> Receiver<T> {
+    pub fn new() -> Self {
+        Receiver(std::marker::PhantomData)
+    }
+}
+
+impl<T> Default for Receiver<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait ValueReceiver {
+    fn function(&self) -> String;
+    fn dependencies(&self) -> Dependencies;
+    /// Makes `handle:method()` call the methods of `owner`, which only handles can.
+    fn methods_metatype(&self, owner: &str) -> String;
+}
+
+impl<T> ValueReceiver for Receiver<T>
+where
+    T: IntoRawConversion + c_marshalling::PtrAsReference + 'static,
+{
+    fn function(&self) -> String {
+        T::create_pointer()
+    }
+    fn dependencies(&self) -> Dependencies {
+        make_dependencies::<T>()
+    }
+    fn methods_metatype(&self, _: &str) -> String {
+        "".to_owned()
+    }
+}
+
+pub trait HandleReceiver {
+    fn function(&self) -> String;
+    fn dependencies(&self) -> Dependencies;
+    fn methods_metatype(&self, owner: &str) -> String;
+}
+
+impl<T: 'static> HandleReceiver for &Receiver<T> {
+    fn function(&self) -> String {
+        <c_marshalling::Handle<T> as IntoRawConversion>::function()
+    }
+    fn dependencies(&self) -> Dependencies {
+        make_dependencies::<c_marshalling::Handle<T>>()
+    }
+    fn methods_metatype(&self, owner: &str) -> String {
+        format!(
+            "ffi.metatype(\"{handle}\", {{ __index = M.{owner} }})\n",
+            handle = <c_marshalling::Handle<T> as Type>::prefixed_typename(),
+            owner = owner
+        )
+    }
+}
+
+macro_rules! primitive_lua_from_native {
+    ($($typ:ty)*) => {
+        $(
+            impl Type for $typ {
+                const IS_PRIMITIVE: bool = true;
+
+                fn typename() -> String {
+                    stringify!($typ).to_owned()
+                }
+                fn c_typename() -> String {
+                    stringify!($typ).to_owned()
+                }
+                fn c_function_argument() -> String {
+                    <Self as Type>::c_typename()
+                }
+                fn c_mut_function_argument() -> String {
+                    <Self as Type>::c_typename()
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                fn function() -> String {
+                    "function(value) return value end".to_owned()
+                }
+                fn gc() -> bool {
+                    false
+                }
+            }
+
+            impl IntoRawConversion for $typ {
 
                 fn function() -> String {
                     "function(value) return value end".to_owned()
@@ -528,6 +1872,7 @@ macro_rules! primitive_slice_lua_native {
         $(
             impl<'a> Type for &'a [$typ] {
                 const IS_PRIMITIVE: bool = false;
+                const IS_INDIRECT: bool = true;
 
                 fn typename() -> String {
                     format!("Slice_{}", stringify!($typ))
@@ -651,7 +1996,7 @@ primitive_slice_lua_to_native!(
     double
 );
 
-impl<'a> IntoRawConversion for &'a [u8] {
+impl IntoRawConversion for &[u8] {
     fn function() -> String {
         format!(
             r#"function(value)
@@ -678,17 +2023,32 @@ end"#,
     }
 }
 
-impl<'a> Type for &'a str {
-    const IS_PRIMITIVE: bool = true;
+/// Non-primitive types which can be passed in slices, `&[T]`. Types with
+/// `#[derive(LuaMarshalling)]` implement this trait.
+pub trait SliceElement: IntoRawConversion {}
+
+impl<T: SliceElement + 'static> Type for &[T] {
+    const IS_PRIMITIVE: bool = false;
+    const IS_INDIRECT: bool = true;
 
     fn typename() -> String {
-        "_str_ptr__".to_owned()
+        format!("Slice_{}", T::typename())
     }
-    fn c_typename() -> String {
-        "char *".to_owned()
+    fn typedeclaration() -> String {
+        format!(
+            r#"typedef struct {{
+    const {c_typename} *ptr;
+    size_t len;
+}} {self_typename};"#,
+            c_typename = T::prefixed_c_typename(),
+            self_typename = Self::prefixed_typename()
+        )
+    }
+    fn dependencies() -> Dependencies {
+        make_dependencies::<T>()
     }
     fn c_function_argument() -> String {
-        format!("const {}", Self::c_typename())
+        format!("const {}*", Self::prefixed_c_typename())
     }
     fn c_mut_function_argument() -> String {
         // Mutable not supported
@@ -696,15 +2056,22 @@ impl<'a> Type for &'a str {
     }
 }
 
-impl<'a> IntoRawConversion for &'a str {
+impl<T: SliceElement + 'static> IntoRawConversion for &[T] {
     fn function() -> String {
-        "function(value) return value end".to_owned()
+        format!(
+            r#"function(value)
+    local f = {create_array}
+    return __typename_{self_typename}(f(value), #value)
+end"#,
+            self_typename = <Self as Type>::typename(),
+            create_array = T::create_array()
+        )
     }
     fn create_pointer() -> String {
-        primitive_type_create_pointer::<Self>()
+        ptr_type_create_pointer::<Self>()
     }
     fn create_array() -> String {
-        primitive_type_create_array::<Self>()
+        ptr_type_create_array::<Self>()
     }
 }
 
@@ -745,3 +2112,255 @@ impl IntoRawConversion for bool {
         primitive_type_create_array::<Self>()
     }
 }
+
+impl Type for () {
+    const IS_PRIMITIVE: bool = true;
+    const IS_NULLABLE: bool = true;
+
+    fn typename() -> String {
+        "__unit".to_owned()
+    }
+    fn c_typename() -> String {
+        stringify!(int8_t).to_owned()
+    }
+    fn c_function_argument() -> String {
+        Self::c_typename()
+    }
+    fn c_mut_function_argument() -> String {
+        Self::c_typename()
+    }
+}
+
+impl FromRawConversion for () {
+    fn function() -> String {
+        "function(value) end".to_owned()
+    }
+    fn gc() -> bool {
+        false
+    }
+}
+
+impl IntoRawConversion for () {
+    fn function() -> String {
+        "function(value) return 0 end".to_owned()
+    }
+    fn create_pointer() -> String {
+        immediate_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        immediate_type_create_array::<Self>()
+    }
+}
+
+impl Type for char {
+    const IS_PRIMITIVE: bool = true;
+
+    fn typename() -> String {
+        "__char".to_owned()
+    }
+    fn c_typename() -> String {
+        stringify!(uint32_t).to_owned()
+    }
+    fn metatype() -> String {
+        // Lua sees a `char` as a string of its UTF-8 encoding, C as its code point.
+        format!(
+            "{}{}",
+            primitive_type_metatype::<Self>(),
+            named_conversions_metatype::<Self>(
+                r#"function(value)
+    if value < 0x80 then
+        return string.char(value)
+    elseif value < 0x800 then
+        return string.char(
+            0xC0 + math.floor(value / 0x40),
+            0x80 + value % 0x40)
+    elseif value < 0x10000 then
+        return string.char(
+            0xE0 + math.floor(value / 0x1000),
+            0x80 + math.floor(value / 0x40) % 0x40,
+            0x80 + value % 0x40)
+    else
+        return string.char(
+            0xF0 + math.floor(value / 0x40000),
+            0x80 + math.floor(value / 0x1000) % 0x40,
+            0x80 + math.floor(value / 0x40) % 0x40,
+            0x80 + value % 0x40)
+    end
+end"#,
+                r#"function(value)
+    local first = type(value) == "string" and value:byte(1) or 0xFF
+    local length, codepoint
+    if first < 0x80 then
+        length, codepoint = 1, first
+    elseif first >= 0xF8 then
+        length = nil
+    elseif first >= 0xF0 then
+        length, codepoint = 4, first - 0xF0
+    elseif first >= 0xE0 then
+        length, codepoint = 3, first - 0xE0
+    elseif first >= 0xC0 then
+        length, codepoint = 2, first - 0xC0
+    end
+    if length == nil or #value ~= length then
+        error("Invalid value for char: "..tostring(value))
+    end
+    for i = 2, length do
+        local byte = value:byte(i)
+        if byte < 0x80 or byte >= 0xC0 then
+            error("Invalid value for char: "..tostring(value))
+        end
+        codepoint = codepoint * 0x40 + byte % 0x40
+    end
+    return codepoint
+end"#,
+            )
+        )
+    }
+    fn c_function_argument() -> String {
+        Self::c_typename()
+    }
+    fn c_mut_function_argument() -> String {
+        Self::c_typename()
+    }
+}
+
+impl FromRawConversion for char {
+    fn function() -> String {
+        named_from_raw_function::<Self>()
+    }
+    fn gc() -> bool {
+        false
+    }
+}
+
+impl IntoRawConversion for char {
+    fn function() -> String {
+        named_into_raw_function::<Self>()
+    }
+    fn create_pointer() -> String {
+        immediate_type_create_pointer::<Self>()
+    }
+    fn create_array() -> String {
+        immediate_type_create_array::<Self>()
+    }
+}
+
+// 128-bit integers are passed as decimal strings, which Lua numbers are converted into.
+macro_rules! decimal_string_lua_native {
+    ($($typ:ty)*) => {
+        $(
+            impl Type for $typ {
+                const IS_PRIMITIVE: bool = true;
+
+                fn typename() -> String {
+                    format!("__{}", stringify!($typ))
+                }
+                fn c_typename() -> String {
+                    "char *".to_owned()
+                }
+                fn c_function_argument() -> String {
+                    format!("const {}", Self::c_typename())
+                }
+                fn c_mut_function_argument() -> String {
+                    Self::c_typename()
+                }
+            }
+
+            impl FromRawConversion for $typ {
+                fn function() -> String {
+                    "ffi.string".to_owned()
+                }
+                fn gc() -> bool {
+                    true
+                }
+            }
+
+            impl IntoRawConversion for $typ {
+                fn function() -> String {
+                    format!(r#"function(value)
+    if type(value) == "number" then
+        if value % 1 ~= 0 then
+            error("Invalid value for {typename}: "..tostring(value))
+        end
+        return string.format("%.0f", value)
+    elseif type(value) == "cdata" then
+        return (tostring(value):gsub("U?LL$", ""))
+    elseif type(value) ~= "string" then
+        error("Invalid value for {typename}: "..tostring(value))
+    end
+    return value
+end"#,
+                        typename = stringify!($typ))
+                }
+                fn create_pointer() -> String {
+                    immediate_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    immediate_type_create_array::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+decimal_string_lua_native!(i128 u128);
+
+// Non-zero integers are passed as their primitive, and zero is rejected by Rust.
+macro_rules! nonzero_lua_native {
+    ($($typ:ident $primitive:ty)*) => {
+        $(
+            impl Type for std::num::$typ {
+                const IS_PRIMITIVE: bool = true;
+
+                // Containers such as `Vec<NonZeroU32>` are declared apart from those of the
+                // primitive, so they need a name of their own.
+                fn typename() -> String {
+                    stringify!($typ).to_owned()
+                }
+                fn c_typename() -> String {
+                    <$primitive as Type>::c_typename()
+                }
+                fn c_function_argument() -> String {
+                    <$primitive as Type>::c_function_argument()
+                }
+                fn c_mut_function_argument() -> String {
+                    <$primitive as Type>::c_mut_function_argument()
+                }
+            }
+
+            impl FromRawConversion for std::num::$typ {
+                fn function() -> String {
+                    <$primitive as FromRawConversion>::function()
+                }
+                fn gc() -> bool {
+                    false
+                }
+            }
+
+            impl IntoRawConversion for std::num::$typ {
+                fn function() -> String {
+                    <$primitive as IntoRawConversion>::function()
+                }
+                fn create_pointer() -> String {
+                    primitive_type_create_pointer::<Self>()
+                }
+                fn create_array() -> String {
+                    primitive_type_create_array::<Self>()
+                }
+            }
+        )*
+    };
+}
+
+nonzero_lua_native!(
+    NonZeroI8 int8_t
+    NonZeroI16 int16_t
+    NonZeroI32 int32_t
+    NonZeroI64 int64_t
+    NonZeroU8 uint8_t
+    NonZeroU16 uint16_t
+    NonZeroU32 uint32_t
+    NonZeroU64 uint64_t
+    NonZeroIsize ssize_t
+    NonZeroUsize size_t
+);
@@ -1,14 +1,17 @@
 local ffi = require("ffi")
 
 ffi.cdef[[
-    char *__lua_bootstrap();
+    char *__lua_bootstrap(char **error);
     void __free_lua_bootstrap(char *);
 ]]
 local rust = ffi.load(arg[1])
 
-local bootstrap = rust.__lua_bootstrap()
+local error_ptr = ffi.new("char *[1]")
+local bootstrap = rust.__lua_bootstrap(error_ptr)
 if bootstrap == nil then
-    error("lua_bootstrap failed")
+    local message = ffi.string(error_ptr[0])
+    rust.__free_lua_bootstrap(error_ptr[0])
+    error("lua_bootstrap failed: "..message)
 end
 ffi.gc(bootstrap, rust.__free_lua_bootstrap)
 print(ffi.string(bootstrap))
@@ -230,8 +230,10 @@ function M.testInvalidUtf8()
 end
 
 function M.testStringWithByteZeros()
-    local status, _ = pcall(unit.string_with_byte_zeros)
-    luaunit.assertFalse(status)
+    luaunit.assertEquals(unit.string_with_byte_zeros(), "String\0containing\0null\0bytes")
+    luaunit.assertEquals(unit.echo_string("a\0b"), "a\0b")
+    luaunit.assertEquals(tonumber(unit.str_length("a\0b")), 3)
+    luaunit.assertEquals(unit.join_strings({ "a\0", "b" }, ","), "a\0,b")
 end
 
 function M.testBooleans()
@@ -297,4 +299,736 @@ function M.testResult()
     luaunit.assertNil(err)
 end
 
+function M.testEnums()
+    luaunit.assertEquals(unit.Color.Red, "Red")
+    luaunit.assertEquals(unit.Color.Blue, "Blue")
+    luaunit.assertEquals(unit.next_color("Red"), "Green")
+    luaunit.assertEquals(unit.next_color(unit.Color.Green), "Blue")
+    luaunit.assertEquals(unit.next_color(unit.Color.Blue), "Red")
+    luaunit.assertEquals(unit.next_color(4), "Red")
+
+    luaunit.assertEquals(unit.Level.Low, 1)
+    luaunit.assertEquals(unit.Level.High, 10)
+    luaunit.assertEquals(unit.toggle_level(unit.Level.Low), unit.Level.High)
+    luaunit.assertEquals(unit.toggle_level("High"), unit.Level.Low)
+
+    local h = unit.make_h("Blue", nil, { "Red", unit.Color.Green }, unit.Level.High)
+    luaunit.assertEquals(h.color, "Blue")
+    luaunit.assertNil(h.option_color)
+    luaunit.assertEquals(h.colors, { "Red", "Green" })
+    luaunit.assertEquals(h.level, 10)
+    luaunit.assertEquals(
+        unit.describe_h(unit.make_h("Red", "Blue", {}, 1)),
+        "H { color: Red, option_color: Some(Blue), colors: [], level: Low }")
+end
+
+function M.testInvalidEnums()
+    local status, _ = pcall(unit.next_color, "Purple")
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.next_color, 3)
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.next_color, nil)
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.make_h, "Red", nil, { "Red", "Purple" }, 1)
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.toggle_level, 2)
+    luaunit.assertFalse(status)
+end
+
+function M.testTaggedEnums()
+    luaunit.assertAlmostEquals(unit.area({ tag = "Circle", radius = 2 }), math.pi * 4, 1e-9)
+    luaunit.assertEquals(unit.area({ tag = "Rectangle", width = 2, height = 3 }), 6)
+    luaunit.assertEquals(unit.area({ tag = "Point" }), 0)
+
+    luaunit.assertEquals(unit.scale_shape({ tag = "Circle", radius = 2 }, 2), { tag = "Circle", radius = 4 })
+    luaunit.assertEquals(
+        unit.scale_shape({ tag = "Rectangle", width = 2, height = 3 }, 0.5),
+        { tag = "Rectangle", width = 1, height = 1.5 })
+    luaunit.assertEquals(unit.scale_shape({ tag = "Point" }, 2), { tag = "Point" })
+    luaunit.assertEquals(unit.area(unit.scale_shape({ tag = "Rectangle", width = 2, height = 3 }, 2)), 24)
+    luaunit.assertEquals(
+        unit.label_circle({ tag = "Rectangle", width = 2, height = 3 }, "area"),
+        { label = "area 6" })
+
+    local events = unit.echo_events({
+        { tag = "Key", 42 },
+        { tag = "Text", "Bilbo", nil },
+        { tag = "Text", "Frodo", unit.make_a("Baggins", 111) },
+        { tag = "Shapes", shapes = { { tag = "Point" }, { tag = "Circle", radius = 1 } } },
+        { tag = "Color", "Blue" },
+        { tag = "Quit" },
+    })
+    luaunit.assertEquals(#events, 6)
+    luaunit.assertEquals(events[1], { tag = "Key", 42 })
+    luaunit.assertEquals(events[2], { tag = "Text", "Bilbo" })
+    luaunit.assertEquals(events[3][1], "Frodo")
+    luaunit.assertEquals(events[3][2].string, "Baggins")
+    luaunit.assertEquals(events[3][2].integer, 111)
+    luaunit.assertEquals(events[4], { tag = "Shapes", shapes = { { tag = "Point" }, { tag = "Circle", radius = 1 } } })
+    luaunit.assertEquals(events[5], { tag = "Color", "Blue" })
+    luaunit.assertEquals(events[6], { tag = "Quit" })
+
+    luaunit.assertEquals(unit.describe_event({ tag = "Key", 42 }), "Key(42)")
+    luaunit.assertEquals(
+        unit.describe_event({ tag = "Text", "Frodo", unit.make_a("Baggins", 111) }),
+        'Text("Frodo", Some(A { string: "Baggins", integer: 111 }))')
+end
+
+function M.testInvalidTaggedEnums()
+    local status, _ = pcall(unit.area, { tag = "Triangle" })
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.area, {})
+    luaunit.assertFalse(status)
+    local status, _ = pcall(unit.echo_events, { { tag = "Color", "Purple" } })
+    luaunit.assertFalse(status)
+end
+
+function M.testNewtypes()
+    luaunit.assertAlmostEquals(unit.add_meters(1.5, 2.25), 3.75)
+    luaunit.assertEquals(unit.next_user_id(41), 42)
+    luaunit.assertEquals(unit.greet("Bilbo"), "Hello Bilbo!")
+
+    local wrapped = unit.wrap_a(unit.make_a("Bilbo", 111))
+    luaunit.assertEquals(wrapped.string, "Bilbo")
+    luaunit.assertEquals(wrapped.integer, 111)
+    local a = unit.unwrap_a(wrapped)
+    luaunit.assertEquals(a.string, "Bilbo")
+    luaunit.assertEquals(a.integer, 111)
+end
+
+function M.testTupleStructs()
+    luaunit.assertEquals(unit.make_pair(42, "Bilbo"), { 42, "Bilbo" })
+    luaunit.assertEquals(unit.describe_pair({ 42, "Bilbo" }), 'Pair(42, "Bilbo")')
+    luaunit.assertEquals(unit.describe_pair(unit.make_pair(1, "Frodo")), 'Pair(1, "Frodo")')
+end
+
+function M.testUnitStructs()
+    luaunit.assertTrue(unit.maybe_acknowledge(true))
+    luaunit.assertNil(unit.maybe_acknowledge(false))
+    luaunit.assertEquals(unit.count_acks({ true, true, true }), 3)
+    luaunit.assertEquals(unit.count_acks({}), 0)
+end
+
+function M.testStructWithNewtypes()
+    local i = unit.make_i(1, "Bilbo", { 2, "Frodo" }, { 3, 4 }, { "Sam" }, true)
+    luaunit.assertEquals(i.id, 1)
+    luaunit.assertEquals(i.name, "Bilbo")
+    luaunit.assertEquals(i.pair, { 2, "Frodo" })
+    luaunit.assertEquals(i.ids, { 3, 4 })
+    luaunit.assertEquals(i.names, { "Sam" })
+    luaunit.assertTrue(i.ack)
+    luaunit.assertEquals(
+        unit.describe_i(i),
+        'I { id: UserId(1), name: Name("Bilbo"), pair: Pair(2, "Frodo"), '
+        ..'ids: [UserId(3), UserId(4)], names: Some([Name("Sam")]), ack: Some(Ack) }')
+    luaunit.assertEquals(
+        unit.describe_i(unit.make_i(1, "Bilbo", { 2, "Frodo" }, {}, nil, nil)),
+        'I { id: UserId(1), name: Name("Bilbo"), pair: Pair(2, "Frodo"), '
+        ..'ids: [], names: None, ack: None }')
+end
+
+function M.testTupleReturns()
+    local quotient, remainder = unit.divide(17, 5)
+    luaunit.assertEquals(quotient, 3)
+    luaunit.assertEquals(remainder, 2)
+
+    local string, integer, boolean = unit.split_a(unit.make_a("Bilbo", 111))
+    luaunit.assertEquals(string, "Bilbo")
+    luaunit.assertEquals(integer, 111)
+    luaunit.assertTrue(boolean)
+end
+
+function M.testTupleArguments()
+    local string, integer = unit.swap({ 42, "Bilbo" })
+    luaunit.assertEquals(string, "Bilbo")
+    luaunit.assertEquals(integer, 42)
+
+    local pair, boolean = unit.nest({ 42, { "Bilbo", false } })
+    luaunit.assertEquals(pair, { 42, "Bilbo" })
+    luaunit.assertFalse(boolean)
+
+    luaunit.assertEquals(
+        unit.zip({ 1, 2, 3 }, { "Red", "Green" }),
+        { { 1, "Red" }, { 2, "Green" } })
+    luaunit.assertEquals(unit.zip({}, {}), {})
+end
+
+function M.testTupleFields()
+    local j = unit.make_j({ 1.5, 2.5 }, { { "Bilbo", unit.make_a("Baggins", 111) }, { "Frodo", nil } })
+    luaunit.assertEquals(j.point, { 1.5, 2.5 })
+    luaunit.assertEquals(#j.labels, 2)
+    luaunit.assertEquals(j.labels[1][1], "Bilbo")
+    luaunit.assertEquals(j.labels[1][2].string, "Baggins")
+    luaunit.assertEquals(j.labels[2], { "Frodo" })
+    luaunit.assertEquals(
+        unit.describe_j(j),
+        'J { point: (1.5, 2.5), labels: [("Bilbo", Some(A { string: "Baggins", integer: 111 })), ("Frodo", None)] }')
+end
+
+function M.testUnitReturns()
+    luaunit.assertEquals(select('#', unit.reset_counter()), 0)
+    unit.add_to_counter(3)
+    unit.add_to_counter(4)
+    luaunit.assertEquals(unit.counter(), 7)
+
+    luaunit.assertEquals(select('#', unit.add_even_to_counter(2)), 0)
+    luaunit.assertEquals(unit.counter(), 9)
+    local value, err = unit.add_even_to_counter(3)
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "3 is odd")
+    luaunit.assertEquals(unit.counter(), 9)
+
+    luaunit.assertErrorMsgContains("panic_without_return failed with status 2", unit.panic_without_return)
+end
+
+function M.testUnitValues()
+    luaunit.assertEquals(unit.units({}), nil)
+    luaunit.assertEquals(select('#', unit.units({ true, true })), 0)
+end
+
+function M.testMaps()
+    luaunit.assertEquals(unit.count_words("the ring the one ring"), { the = 2, ring = 2, one = 1 })
+    luaunit.assertEquals(unit.count_words(""), {})
+
+    luaunit.assertEquals(unit.sum_values({ a = 1, b = 2, c = 39 }), 42)
+    luaunit.assertEquals(unit.sum_values({}), 0)
+
+    local squares = unit.squares(3)
+    luaunit.assertEquals(squares, { 1, 4, 9 })
+    luaunit.assertEquals(squares[2], 4)
+
+    luaunit.assertEquals(
+        unit.describe_btree_map({ [3] = "c", [1] = "a", [-2] = "b" }),
+        '{-2: "b", 1: "a", 3: "c"}')
+end
+
+function M.testLargeIntegerKeys()
+    local large = { 9007199254740993ULL, 9007199254740994ULL, 18446744073709551615ULL }
+    local indices = {}
+    for key, index in pairs(unit.key_indices({ 1, large[1], large[2], large[3] })) do
+        indices[tostring(key)] = index
+    end
+    luaunit.assertEquals(indices, {
+        ["1"] = 0,
+        ["9007199254740993ULL"] = 1,
+        ["9007199254740994ULL"] = 2,
+        ["18446744073709551615ULL"] = 3,
+    })
+    luaunit.assertEquals(unit.describe_u64_set({ 1, large[1], large[2] }), "{1, 9007199254740993, 9007199254740994}")
+end
+
+function M.testStructWithMaps()
+    local k = unit.make_k(
+        { bilbo = unit.make_a("Bilbo", 111), frodo = unit.make_a("Frodo", 33) },
+        { [1] = { "Sam", "Merry" }, [7] = {} })
+    luaunit.assertEquals(k.members.bilbo.string, "Bilbo")
+    luaunit.assertEquals(k.members.frodo.integer, 33)
+    luaunit.assertEquals(k.groups, { [1] = { "Sam", "Merry" }, [7] = {} })
+    luaunit.assertEquals(
+        unit.describe_k(k),
+        'members: {"bilbo": A { string: "Bilbo", integer: 111 }, "frodo": A { string: "Frodo", integer: 33 }}, '..
+        'groups: {1: ["Sam", "Merry"], 7: []}')
+end
+
+function M.testSets()
+    luaunit.assertEquals(unit.split_tags("admin, user, admin"), { admin = true, user = true })
+
+    luaunit.assertEquals(unit.describe_btree_set({ [3] = true, [1] = true }), "{1, 3}")
+    luaunit.assertEquals(unit.describe_btree_set({ 3, 1, 3 }), "{1, 3}")
+    luaunit.assertEquals(unit.describe_btree_set({}), "{}")
+    luaunit.assertEquals(unit.describe_btree_set({ [1] = true, [2] = true }), "{1, 2}")
+    luaunit.assertEquals(unit.describe_btree_set({ [1] = true, [3] = false }), "{1}")
+
+    luaunit.assertEquals(unit.describe_bool_set({ true, false }), "{false, true}")
+    luaunit.assertEquals(unit.describe_bool_set({ false }), "{false}")
+    luaunit.assertEquals(unit.describe_bool_set({ [false] = true }), "{false}")
+end
+
+function M.testStructWithSets()
+    local l = unit.make_l({ read = true, write = true }, { 42, 7 })
+    luaunit.assertEquals(l.tags, { read = true, write = true })
+    luaunit.assertEquals(l.ids, { [7] = true, [42] = true })
+    luaunit.assertTrue(l.ids[42])
+    luaunit.assertEquals(unit.describe_l(l), 'tags: {"read", "write"}, ids: {7, 42}')
+
+    local l = unit.make_l({ admin = true, guest = false }, {})
+    luaunit.assertEquals(l.tags, { admin = true })
+end
+
+function M.testRecursiveStructs()
+    local tree = unit.make_tree("root", 3)
+    luaunit.assertEquals(tree.name, "root")
+    luaunit.assertEquals(#tree.children, 3)
+    luaunit.assertEquals(tree.children[3].children[2].name, "root.2.1")
+    luaunit.assertEquals(unit.count_nodes(tree), 8)
+
+    local list = unit.make_list({ "a", "b", "c" })
+    luaunit.assertEquals(list.name, "a")
+    luaunit.assertEquals(list.next.next.name, "c")
+    luaunit.assertNil(list.next.next.next)
+    luaunit.assertEquals(unit.count_nodes(list), 3)
+    luaunit.assertNil(unit.make_list({}))
+
+    luaunit.assertEquals(unit.count_nodes({ name = "x", children = { { name = "y", children = {} } } }), 2)
+end
+
+function M.testRecursiveEnums()
+    local expression = { tag = "Sum", terms = {
+        { tag = "Number", 1.5 },
+        { tag = "Add", { tag = "Number", 2 }, { tag = "Negate", { tag = "Number", 0.5 } } },
+    } }
+    luaunit.assertEquals(unit.evaluate(expression), 3)
+
+    local negated = unit.negate(expression)
+    luaunit.assertEquals(negated.tag, "Negate")
+    luaunit.assertEquals(negated[1].terms[2][2][1], { tag = "Number", 0.5 })
+    luaunit.assertEquals(unit.evaluate(negated), -3)
+end
+
+function M.testBoxes()
+    local a = unit.boxed_a(unit.make_a("Bilbo", 111))
+    luaunit.assertEquals(a.string, "Bilbo")
+    luaunit.assertEquals(a.integer, 111)
+end
+
+function M.testIndirectlyRecursiveTypes()
+    luaunit.assertEquals(unit.count_labelled({ children = {
+        { "a", { children = { { "b", { children = {} } } } } },
+        { "c", { children = {} } },
+    } }), 3)
+
+    luaunit.assertEquals(
+        unit.describe_directory({ name = "home", files = {
+            { name = "notes" },
+            { name = "documents", directory = { name = "documents", files = {} } },
+        } }),
+        'Directory { name: "home", files: [File { name: "notes", directory: None }, '..
+        'File { name: "documents", directory: Some(Directory { name: "documents", files: [] }) }] }')
+end
+
+function M.testNonPrimitiveSlices()
+    luaunit.assertEquals(unit.join_strings({ "Bilbo", "Frodo", "Sam" }, ", "), "Bilbo, Frodo, Sam")
+    luaunit.assertEquals(unit.join_strings({}, ", "), "")
+    luaunit.assertEquals(unit.join_strs({ "a", "b", "c" }), "abc")
+
+    luaunit.assertEquals(unit.sum_integers_of({ unit.make_a("Bilbo", 111), { string = "Frodo", integer = 33 } }), 144)
+    luaunit.assertEquals(unit.sum_integers_of({}), 0)
+
+    luaunit.assertEquals(
+        unit.describe_slices({ "Red", "Blue" }, { 1, 2 }, { { tag = "Point" }, { tag = "Circle", radius = 1.5 } }),
+        "[Red, Blue] [UserId(1), UserId(2)] [Point, Circle { radius: 1.5 }]")
+end
+
+function M.testVecOfOptions()
+    luaunit.assertEquals(unit.fill_gaps({ 1, unit.null, 3, unit.null }, 0), { 1, 0, 3, 0 })
+    luaunit.assertEquals(unit.fill_gaps({ n = 4, 1, nil, 3 }, 0), { 1, 0, 3, 0 })
+    luaunit.assertEquals(unit.fill_gaps({}, 0), {})
+
+    local samples = unit.every_other(5)
+    luaunit.assertEquals(samples.n, 5)
+    luaunit.assertEquals(samples, { n = 5, 0, nil, 2, nil, 4 })
+    luaunit.assertEquals(unit.fill_gaps(samples, -1), { 0, -1, 2, -1, 4 })
+    luaunit.assertEquals(unit.every_other(0), { n = 0 })
+    luaunit.assertEquals(tostring(unit.null), "null")
+end
+
+function M.testStructWithVecOfOptions()
+    local readings = unit.echo_readings({ samples = { 1.5, unit.null, 2.5 }, labels = { n = 2, nil, "b" } })
+    luaunit.assertEquals(readings.samples, { n = 3, 1.5, nil, 2.5 })
+    luaunit.assertEquals(readings.labels, { n = 2, nil, "b" })
+    luaunit.assertEquals(
+        unit.describe_readings(readings),
+        'Readings { samples: [Some(1.5), None, Some(2.5)], labels: [None, Some("b")] }')
+end
+
+function M.testResultArguments()
+    luaunit.assertEquals(unit.increment_result({ ok = 41 }), 42)
+    local value, err = unit.increment_result({ err = "failed" })
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "failed")
+
+    luaunit.assertEquals(unit.increment_result({ unit.increment_result({ ok = 1 }) }), 3)
+    value, err = unit.increment_result({ unit.increment_result({ err = "failed" }) })
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "failed")
+
+    luaunit.assertEquals(
+        unit.describe_result({ unit.maybe_make_a("Bilbo", 111) }),
+        'Ok(A { string: "Bilbo", integer: 111 })')
+    luaunit.assertEquals(
+        unit.describe_result({ err = { string = "Frodo" } }),
+        'Err(B { string: Some("Frodo"), integer: None })')
+end
+
+function M.testStructWithResults()
+    local outcome = unit.echo_outcome({
+        result = { err = "failed" },
+        results = { { ok = "a" }, { ok = unit.null }, { err = 42 }, { err = unit.null } },
+    })
+    luaunit.assertEquals(outcome.result, { err = "failed" })
+    luaunit.assertEquals(outcome.results, { { ok = "a" }, {}, { err = 42 }, { err = unit.null } })
+    luaunit.assertEquals(
+        unit.describe_outcome(outcome),
+        'Outcome { result: Err("failed"), results: [Ok(Some("a")), Ok(None), Err(Some(42)), Err(None)] }')
+end
+
+function M.testChars()
+    luaunit.assertEquals(unit.next_char("a"), "b")
+    luaunit.assertEquals(unit.next_char("\xC3\xA9"), "\xC3\xAA")
+    luaunit.assertEquals(unit.chars_of("a\xC3\xA9\xE2\x82\xAC\xF0\x9F\x98\x80"),
+        { "a", "\xC3\xA9", "\xE2\x82\xAC", "\xF0\x9F\x98\x80" })
+    luaunit.assertEquals(unit.string_of({ "a", "\xC3\xA9", "\xE2\x82\xAC", "\xF0\x9F\x98\x80" }),
+        "a\xC3\xA9\xE2\x82\xAC\xF0\x9F\x98\x80")
+    luaunit.assertErrorMsgContains("Invalid value for char", unit.next_char, "ab")
+    luaunit.assertErrorMsgContains("Invalid value for char", unit.next_char, "")
+    luaunit.assertErrorMsgContains("Invalid value for char", unit.next_char, "\xC3")
+    -- Surrogates are valid UTF-8 sequences but not chars.
+    luaunit.assertErrorMsgContains("failed with status 1", unit.next_char, "\xED\xA0\x80")
+end
+
+function M.test128BitIntegers()
+    luaunit.assertEquals(unit.add_i128(1, 2), "3")
+    luaunit.assertEquals(
+        unit.add_i128("-170141183460469231731687303715884105727", -1),
+        "-170141183460469231731687303715884105728")
+    luaunit.assertEquals(unit.add_i128(9007199254740993LL, "1"), "9007199254740994")
+    luaunit.assertEquals(unit.max_u128(), "340282366920938463463374607431768211455")
+    luaunit.assertErrorMsgContains("failed with status 1", unit.add_i128, "one", 1)
+    luaunit.assertErrorMsgContains("Invalid value for i128: 1.5", unit.add_i128, 1.5, 1)
+    luaunit.assertErrorMsgContains("Invalid value for i128: inf", unit.add_i128, math.huge, 1)
+    luaunit.assertErrorMsgContains("Invalid value for i128: nil", unit.add_i128, nil, 1)
+    luaunit.assertErrorMsgContains("Invalid value for u128: true", unit.echo_scalars,
+        { letter = "a", large = 0, natural = true, count = 1 })
+end
+
+function M.testNonZeroIntegers()
+    luaunit.assertEquals(unit.double_count(21), 42)
+    luaunit.assertErrorMsgContains("failed with status 1", unit.double_count, 0)
+    local count, err = unit.zero_count()
+    luaunit.assertNil(count)
+    luaunit.assertEquals(err, "zero")
+    luaunit.assertEquals(unit.double_counts({ 1, 21 }), { 2, 42 })
+    luaunit.assertErrorMsgContains("failed with status 1", unit.double_counts, { 1, 0 })
+    luaunit.assertEquals(unit.first_count({ 7, 0 }), 7)
+    luaunit.assertNil(unit.first_count({ 0 }))
+end
+
+function M.testStructWithScalars()
+    local scalars = unit.echo_scalars({ letter = "\xC3\xA9", large = -1, natural = "1", count = 3 })
+    luaunit.assertEquals(scalars, { letter = "\xC3\xA9", large = "-1", natural = "1", count = 3 })
+    luaunit.assertEquals(
+        unit.describe_scalars(scalars),
+        "Scalars { letter: '\xC3\xA9', large: -1, natural: 1, count: 3 }")
+    luaunit.assertErrorMsgContains("failed with status 1",
+        unit.echo_scalars, { letter = "a", large = 0, natural = 0, count = 0 })
+end
+
+function M.testCStrings()
+    luaunit.assertEquals(unit.make_c_string("Bilbo"), "Bilbo")
+    local ok, err = unit.make_c_string("a\0b")
+    luaunit.assertNil(ok)
+    luaunit.assertEquals(err, "nul byte found in provided data at position: 1")
+    luaunit.assertEquals(tonumber(unit.c_string_length("Bilbo")), 5)
+    -- C strings end at the first zero-byte.
+    luaunit.assertEquals(tonumber(unit.c_string_length("a\0b")), 1)
+end
+
+function M.testBytes()
+    local bytes = unit.all_bytes()
+    luaunit.assertEquals(type(bytes), "string")
+    luaunit.assertEquals(#bytes, 256)
+    luaunit.assertEquals(bytes:byte(1), 0)
+    luaunit.assertEquals(bytes:byte(256), 255)
+    luaunit.assertEquals(unit.reverse_bytes(bytes):byte(1), 255)
+    luaunit.assertEquals(unit.reverse_bytes("a\0\255"), "\255\0a")
+    luaunit.assertEquals(unit.reverse_bytes(""), "")
+    luaunit.assertEquals(unit.split_bytes("abc\0e", 2), { "ab", "c\0", "e" })
+    luaunit.assertEquals(unit.maybe_bytes("\255"), "\255")
+    luaunit.assertNil(unit.maybe_bytes(nil))
+    luaunit.assertEquals(
+        unit.invert_image({ name = "dot", pixels = "\0\255" }),
+        { name = "dot", pixels = "\255\0" })
+end
+
+function M.testPaths()
+    luaunit.assertEquals(unit.file_name("/tmp/legacy-\xE9.txt"), "legacy-\xE9.txt")
+    luaunit.assertNil(unit.file_name("/"))
+    luaunit.assertEquals(unit.join_paths("/tmp", { "a\xFF", "b" }), "/tmp/a\xFF/b")
+    luaunit.assertEquals(unit.os_str_length("\xFF\xFE"), 2)
+    luaunit.assertEquals(unit.echo_os_string("\xFF\0\xFE"), "\xFF\0\xFE")
+    luaunit.assertEquals(unit.api_path_depth({ segments = { "users", "42" } }), 2)
+    luaunit.assertEquals(unit.extension("/tmp/legacy.t\xE9xt"), "t\xE9xt")
+    luaunit.assertTrue(unit.is_absolute("/tmp"))
+    luaunit.assertFalse(unit.is_absolute("tmp"))
+    luaunit.assertEquals(unit.native_str_length("\xFF\xFE"), 2)
+end
+
+function M.testStringLikeTypes()
+    luaunit.assertEquals(unit.intern("Bilbo"), "Bilbo")
+    luaunit.assertEquals(unit.intern_all({ "Bilbo", "Frodo\0" }), { "Bilbo", "Frodo\0" })
+    luaunit.assertEquals(unit.lookup_label(0), "zero")
+    luaunit.assertEquals(unit.lookup_label(7), "key 7")
+    luaunit.assertEquals(unit.shout("Bilbo"), "BILBO")
+    luaunit.assertEquals(unit.shared_length("Bilbo"), 5)
+    local interned = unit.echo_interned({ name = "a", label = "b", boxed = "c", shared = "d" })
+    luaunit.assertEquals(interned, { name = "a", label = "b", boxed = "c", shared = "d" })
+    luaunit.assertEquals(
+        unit.describe_interned(interned),
+        'Interned { name: "a", label: "b", boxed: "c", shared: "d" }')
+end
+
+function M.testArrays()
+    luaunit.assertEquals(unit.scale({ 1, 2, 3 }, 2), { 2, 4, 6 })
+    luaunit.assertErrorMsgContains("Invalid length for Array_float_3: 2", unit.scale, { 1, 2 }, 2)
+    luaunit.assertErrorMsgContains("Invalid length for Array_float_3: 4", unit.scale, { 1, 2, 3, 4 }, 2)
+    luaunit.assertEquals(unit.identity_matrix(), { { 1, 0 }, { 0, 1 } })
+    luaunit.assertEquals(unit.transpose({ { 1, 2 }, { 3, 4 } }), { { 1, 3 }, { 2, 4 } })
+    luaunit.assertEquals(unit.join_pair({ "Bilbo", "Frodo" }), "Bilbo & Frodo")
+    luaunit.assertEquals(unit.maybe_pair({ n = 2, nil, 2 }), { n = 2, nil, 2 })
+    luaunit.assertNil(unit.maybe_pair(nil))
+end
+
+function M.testStructWithArrays()
+    local pixel = unit.echo_pixel({ position = { 1, 2, 3 }, color = { 255, 0, 0, 255 } })
+    luaunit.assertEquals(pixel, { position = { 1, 2, 3 }, color = { 255, 0, 0, 255 } })
+    luaunit.assertEquals(unit.colors({ pixel, { position = { 0, 0, 0 }, color = { 1, 2, 3, 4 } } }),
+        { { 255, 0, 0, 255 }, { 1, 2, 3, 4 } })
+    luaunit.assertErrorMsgContains("Invalid length for Array_uint8_t_4: 3",
+        unit.echo_pixel, { position = { 1, 2, 3 }, color = { 255, 0, 0 } })
+end
+
+function M.testGenericStructs()
+    local a = unit.first_page_of_a({ { string = "Bilbo", integer = 111 } })
+    luaunit.assertEquals(a, { items = { { string = "Bilbo", integer = 111 } }, next = 2 })
+    local strings = unit.last_page_of_strings({ "Frodo" })
+    luaunit.assertEquals(strings, { items = { "Frodo" } })
+    luaunit.assertEquals(
+        unit.describe_pages(a, strings),
+        'Page { items: [A { string: "Bilbo", integer: 111 }], next: Some(2) } Page { items: ["Frodo"], next: None }')
+    luaunit.assertEquals(unit.swap_couple({ 42, "Bilbo" }), { "Bilbo", 42 })
+    luaunit.assertEquals(
+        unit.nested_pages({ { items = { 1, 2 } }, { items = {}, next = 3 } }),
+        { items = { { items = { 1, 2 } }, { items = {}, next = 3 } } })
+end
+
+function M.testTypesOfTheSameName()
+    luaunit.assertEquals(unit.upgrade_request({ path = "/" }, unit.Method.Get), { path = "/", retries = 3 })
+    luaunit.assertEquals(unit.upgrade_request({ path = "/" }, "Post"), { path = "/" })
+    luaunit.assertEquals(
+        unit.downgrade_requests({ { path = "/a", retries = 1 }, { path = "/b" } }),
+        { { path = "/a" }, { path = "/b" } })
+    luaunit.assertErrorMsgContains("Invalid value for Method: Put", unit.upgrade_request, { path = "/" }, "Put")
+end
+
+function M.testHandles()
+    local index = unit.new_index({ "Bilbo", "Frodo" })
+    luaunit.assertEquals(tonumber(unit.index_position(index, "Frodo")), 1)
+    luaunit.assertNil(unit.index_position(index, "Sam"))
+    luaunit.assertEquals(tonumber(unit.index_cached(index)), 2)
+    unit.index_insert(index, "Sam")
+    luaunit.assertEquals(tonumber(unit.index_position(index, "Sam")), 2)
+    luaunit.assertErrorMsgContains("index_cached failed with status 1", unit.index_cached, nil)
+end
+
+function M.testHandlesAreBorrowedLikeRefCells()
+    local index = unit.new_index({ "Bilbo" })
+    luaunit.assertTrue(unit.index_equals(index, index))
+    luaunit.assertErrorMsgContains("Index.merge failed with status 1", index.merge, index, index)
+    luaunit.assertEquals(index:words(), { "Bilbo" })
+    index:merge(unit.new_index({ "Frodo" }))
+    luaunit.assertEquals(index:words(), { "Bilbo", "Frodo" })
+end
+
+function M.testBorrowedHandlesCannotBeReturned()
+    collectgarbage()
+    local dropped = unit.dropped_indices()
+    local index = unit.new_index({ "Bilbo" })
+    luaunit.assertErrorMsgContains("swap_index failed with status 1", unit.swap_index, index, { "Frodo" })
+    luaunit.assertEquals(index:words(), { "Bilbo" })
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 1)
+    index = nil
+    collectgarbage()
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 2)
+end
+
+function M.testNestedHandles()
+    local indices = unit.split_index({ "Bilbo", "Frodo" })
+    luaunit.assertEquals(#indices, 2)
+    luaunit.assertEquals(tonumber(unit.index_position(indices[2], "Frodo")), 0)
+    local index = unit.try_new_index({ "Bilbo" })
+    luaunit.assertEquals(tonumber(unit.index_position(index, "Bilbo")), 0)
+    local value, err = unit.try_new_index({})
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "An index needs words")
+end
+
+function M.testHandlesAreDroppedWhenCollected()
+    collectgarbage()
+    local dropped = unit.dropped_indices()
+    local indices = unit.split_index({ "Bilbo", "Frodo" })
+    local index = unit.new_index({})
+    luaunit.assertEquals(unit.dropped_indices(), dropped)
+    indices, index = nil, nil
+    collectgarbage()
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 3)
+end
+
+function M.testMethods()
+    local index = unit.Index.new({ "Bilbo" })
+    luaunit.assertEquals(tonumber(index:len()), 1)
+    luaunit.assertFalse(index:is_empty())
+    index:push("Frodo")
+    luaunit.assertEquals(index:words(), { "Bilbo", "Frodo" })
+    index:merge(unit.Index.new({ "Sam" }))
+    luaunit.assertEquals(unit.Index.words(index), { "Bilbo", "Frodo", "Sam" })
+    luaunit.assertEquals(tonumber(unit.index_position(index, "Sam")), 2)
+    luaunit.assertErrorMsgContains("Index.len failed with status 1", unit.Index.len, nil)
+end
+
+function M.testAssociatedFunctions()
+    local a = unit.A.with_integer(111)
+    luaunit.assertEquals(a, { string = "111", integer = 111 })
+    luaunit.assertEquals(unit.A.describe(a), "111 111")
+    luaunit.assertFalse(unit.A.is_answer(a))
+    luaunit.assertTrue(unit.A.is_answer({ string = "", integer = 42 }))
+    luaunit.assertEquals(unit.Color.next("Blue"), "Red")
+    luaunit.assertEquals(unit.Color.next(unit.Color.Red), unit.Color.Green)
+    luaunit.assertNil(unit.A.hidden)
+end
+
+function M.testCallbacks()
+    luaunit.assertEquals(unit.filter_integers({ 1, 2, 3, 4 }, function(value) return value % 2 == 0 end), { 2, 4 })
+    luaunit.assertEquals(
+        unit.sort_strings({ "Frodo", "Bilbo", "Sam" }, function(a, b) return a < b and -1 or (a > b and 1 or 0) end),
+        { "Bilbo", "Frodo", "Sam" })
+    local visited = {}
+    unit.visit_a({ { string = "Bilbo", integer = 111 }, { string = "Frodo", integer = 33 } }, function(a)
+        table.insert(visited, a.string)
+    end)
+    luaunit.assertEquals(visited, { "Bilbo", "Frodo" })
+    luaunit.assertEquals(unit.build_a(function() return { string = "Sam", integer = 38 } end),
+        { string = "Sam", integer = 38 })
+end
+
+function M.testCallbackErrors()
+    luaunit.assertEquals(unit.try_format_integers({ 1, 2 }, tostring), { "1", "2" })
+    local value, err = unit.try_format_integers({ 1, 2 }, function(value)
+        if value == 2 then
+            error("Two is not allowed", 0)
+        end
+        return tostring(value)
+    end)
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "Formatting failed: Two is not allowed")
+    local ok, err = pcall(unit.filter_integers, { 1 }, function() error("Fails", 0) end)
+    luaunit.assertFalse(ok)
+    luaunit.assertEquals(err, "Fails")
+    local ok, err = pcall(unit.filter_integers, { 1 }, function() error({ code = 42 }) end)
+    luaunit.assertFalse(ok)
+    luaunit.assertEquals(err, { code = 42 })
+    -- The callback is freed even if converting another argument fails, otherwise the
+    -- callback slots run out.
+    for _ = 1, 2000 do
+        luaunit.assertFalse(pcall(unit.sort_strings, 5, function() return 0 end))
+    end
+    for _ = 1, 20000 do
+        unit.filter_integers({ 1 }, function() return true end)
+    end
+    local value, err = unit.try_format_integers({ 1 }, function(value) return { value } end)
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err:sub(1, 18), "Formatting failed:")
+    -- Errors of callbacks which were handled are not raised again when the call fails.
+    local format = function(value)
+        if value == 1 then
+            error("One is not allowed", 0)
+        end
+        return tostring(value)
+    end
+    luaunit.assertErrorMsgContains("swap_formatted_index failed with status 1",
+        unit.swap_formatted_index, unit.new_index({}), { 1, 2 }, format)
+end
+
+function M.testReturnedClosures()
+    local matches = unit.prefix_matcher("Bil")
+    luaunit.assertTrue(matches("Bilbo"))
+    luaunit.assertFalse(matches("Frodo"))
+    local greet = unit.greeter({ string = "Hello", integer = 0 })
+    luaunit.assertEquals(greet({ string = "Sam", integer = 38 }, 2), "Hello SamHello Sam")
+    local divide = unit.divider(2)
+    luaunit.assertEquals(divide(7), 3)
+    local value, err = unit.divider(0)(7)
+    luaunit.assertNil(value)
+    luaunit.assertEquals(err, "Cannot divide 7 by 0")
+    luaunit.assertEquals(unit.range_maker(3)(), { 0, 1, 2 })
+    luaunit.assertErrorMsgContains("Closure failed with status 2", unit.panicking_closure(), 1)
+end
+
+function M.testReturnedClosuresAreDroppedWhenCollected()
+    collectgarbage()
+    local dropped = unit.dropped_indices()
+    local matches = unit.word_matcher({ "Bilbo" })
+    luaunit.assertTrue(matches("Bilbo"))
+    luaunit.assertEquals(unit.dropped_indices(), dropped)
+    matches = nil
+    collectgarbage()
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 1)
+end
+
+function M.testIterators()
+    local values = {}
+    for value in unit.scan(2, 5) do
+        table.insert(values, value)
+    end
+    luaunit.assertEquals(values, { 2, 3, 4 })
+    local strings = {}
+    for a in unit.scan_a({ { string = "Bilbo", integer = 111 }, { string = "Frodo", integer = 33 } }) do
+        table.insert(strings, a.string)
+    end
+    luaunit.assertEquals(strings, { "Bilbo", "Frodo" })
+    local next_value = unit.scan(0, 1)
+    luaunit.assertEquals(next_value(), 0)
+    luaunit.assertNil(next_value())
+    luaunit.assertNil(next_value())
+end
+
+function M.testIteratorsOfNullableItems()
+    local options = {}
+    for value in unit.scan_options({ n = 3, 1, nil, 3 }) do
+        table.insert(options, value)
+    end
+    luaunit.assertEquals(options, { 1, unit.null, 3 })
+    local results = {}
+    for result in unit.parse_integers({ "1", "x", "3" }) do
+        table.insert(results, result)
+    end
+    luaunit.assertEquals(results, { { ok = 1 }, { err = "Not an integer: x" }, { ok = 3 } })
+end
+
+function M.testIteratorsAreDropped()
+    collectgarbage()
+    local dropped = unit.dropped_indices()
+    local words = {}
+    for word in unit.index_words({ "Bilbo", "Frodo" }) do
+        table.insert(words, word)
+    end
+    luaunit.assertEquals(words, { "Bilbo", "Frodo" })
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 1)
+    for word in unit.index_words({ "Bilbo", "Frodo" }) do
+        break
+    end
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 1)
+    collectgarbage()
+    luaunit.assertEquals(unit.dropped_indices(), dropped + 2)
+    for natural in unit.naturals() do
+        if natural == 1000 then
+            break
+        end
+    end
+end
+
+function M.testTypeHelpersDoNotLeakIntoGlobals()
+    luaunit.assertNil(rawget(_G, "__typename_rust_unit__ffi__A"))
+    luaunit.assertEquals(unit.make_a("Bilbo", 111), { string = "Bilbo", integer = 111 })
+end
+
 return M
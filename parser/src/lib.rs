@@ -28,104 +28,705 @@ pub fn uses(items: &[syn::Item]) -> Vec<quote::Tokens> {
 pub struct Argument {
     pub ident: syn::Ident,
     pub typ: quote::Tokens,
+    /// The element type of slice arguments, `&[T]`.
+    pub slice_element: Option<quote::Tokens>,
+    /// The referenced type of reference arguments, `&T`, other than `&str`, `&Path` and `&OsStr`.
+    pub referent: Option<quote::Tokens>,
+    /// Whether the reference argument is mutable, `&mut T`.
+    pub mutable: bool,
+    /// The callback of callback arguments, such as `&dyn Fn(A) -> R` or `impl FnMut(&A)`.
+    pub callback: Option<Callback>,
+    /// Whether the argument is a `&self` or `&mut self` receiver, whose type is the owner.
+    /// It is copied if the owner has value marshalling and borrowed from a `Handle` otherwise.
+    pub receiver: bool,
+}
+
+impl Argument {
+    /// The type marshalled to Rust, which for reference arguments is the referenced type and
+    /// for callback arguments is the callback declared as `fn(A) -> R`.
+    pub fn marshalled_typ(&self) -> &quote::Tokens {
+        match self.callback {
+            Some(ref callback) => &callback.typ,
+            None => self.referent.as_ref().unwrap_or(&self.typ),
+        }
+    }
+}
+
+pub struct Callback {
+    /// The callback declared as `fn(A) -> R`, with reference arguments replaced by their
+    /// owned type and `Result<R, E>` by `R`.
+    pub typ: quote::Tokens,
+    /// The declared argument types and whether they are references, which are converted
+    /// with `ToOwned`.
+    pub args: Vec<(quote::Tokens, bool)>,
+    /// The declared return type.
+    pub ret: quote::Tokens,
+    /// Whether the callback returns a `Result`, into which errors raised by it are converted.
+    pub fallible: bool,
+    /// How the callback is passed, `&`, `&mut` or by value.
+    pub borrow: quote::Tokens,
+}
+
+/// Finds the arguments of the first of the `traits` among `bounds`, e.g. `(A, B) -> R` of
+/// `Fn(A, B) -> R`.
+fn signature<'a>(
+    bounds: &'a syn::punctuated::Punctuated<syn::TypeParamBound, syn::token::Add>,
+    traits: &[&str],
+) -> Option<&'a syn::ParenthesizedGenericArguments> {
+    bounds
+        .iter()
+        .filter_map(|bound| match *bound {
+            syn::TypeParamBound::Trait(ref bound) => bound.path.segments.last(),
+            _ => None,
+        })
+        .map(|segment| segment.into_value())
+        .find(|segment| traits.contains(&segment.ident.as_ref()))
+        .map(|segment| match segment.arguments {
+            syn::PathArguments::Parenthesized(ref arguments) => arguments,
+            _ => panic!("Expected the arguments of {}", segment.ident),
+        })
+}
+
+/// The declared argument types of a signature, with their marshalled type and whether they
+/// are references, which are marshalled as their owned type.
+fn signature_args(
+    arguments: &syn::ParenthesizedGenericArguments,
+) -> Vec<(quote::Tokens, quote::Tokens, bool)> {
+    arguments
+        .inputs
+        .iter()
+        .map(|arg| match *arg {
+            syn::Type::Reference(::syn::TypeReference {
+                elem: ref ty,
+                mutability: None,
+                ..
+            }) => (
+                quote! { #arg },
+                quote! { <#ty as std::borrow::ToOwned>::Owned },
+                true,
+            ),
+            _ => (quote! { #arg }, quote! { #arg }, false),
+        })
+        .collect()
+}
+
+/// Parses the `Fn`, `FnMut` or `FnOnce` bound of callback arguments.
+fn parse_callback(
+    bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::token::Add>,
+    borrow: quote::Tokens,
+) -> Callback {
+    let arguments = signature(bounds, &["Fn", "FnMut", "FnOnce"])
+        .expect("Callback: Trait arguments can only be Fn, FnMut or FnOnce");
+    let args = signature_args(arguments);
+    let (ret, value, fallible) = match arguments.output {
+        syn::ReturnType::Default => (quote! { () }, quote! { () }, false),
+        syn::ReturnType::Type(_, ref ty) => {
+            let ok = match **ty {
+                syn::Type::Path(ref path) => path
+                    .path
+                    .segments
+                    .last()
+                    .map(|segment| segment.into_value())
+                    .filter(|segment| segment.ident.as_ref() == "Result")
+                    .and_then(|segment| match segment.arguments {
+                        syn::PathArguments::AngleBracketed(ref arguments) => {
+                            arguments.args.first().map(|arg| arg.into_value())
+                        }
+                        _ => None,
+                    }),
+                _ => None,
+            };
+            match ok {
+                Some(ok) => (quote! { #ty }, quote! { #ok }, true),
+                None => (quote! { #ty }, quote! { #ty }, false),
+            }
+        }
+    };
+    let owned = args.iter().map(|(_, owned, _)| owned);
+    Callback {
+        typ: quote! { fn(#(#owned),*) -> #value },
+        args: args
+            .iter()
+            .map(|&(ref declared, _, is_reference)| (declared.clone(), is_reference))
+            .collect(),
+        ret,
+        fallible,
+        borrow,
+    }
+}
+
+pub struct Closure {
+    /// The closure marshalled as `c_marshalling::Closure<fn(A) -> R>`, with reference
+    /// arguments replaced by their owned type.
+    pub typ: quote::Tokens,
+    /// The marshalled argument types and whether they are declared as references.
+    pub args: Vec<(quote::Tokens, bool)>,
+    /// Whether the closure wraps a returned iterator, which it advances on each call.
+    pub iterator: bool,
+}
+
+/// Finds the `Item` of an `Iterator` among `bounds`.
+fn iterator_item(
+    bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::token::Add>,
+) -> Option<&syn::Type> {
+    bounds
+        .iter()
+        .filter_map(|bound| match *bound {
+            syn::TypeParamBound::Trait(ref bound) => bound.path.segments.last(),
+            _ => None,
+        })
+        .map(|segment| segment.into_value())
+        .filter(|segment| segment.ident.as_ref() == "Iterator")
+        .filter_map(|segment| match segment.arguments {
+            syn::PathArguments::AngleBracketed(ref arguments) => Some(arguments),
+            _ => None,
+        })
+        .flat_map(|arguments| arguments.args.iter())
+        .filter_map(|arg| match *arg {
+            syn::GenericArgument::Binding(ref binding) if binding.ident.as_ref() == "Item" => {
+                Some(&binding.ty)
+            }
+            _ => None,
+        })
+        .next()
+}
+
+/// Parses returned closures, `Box<dyn Fn(A) -> R>` or `impl Fn(A) -> R`, and iterators,
+/// `Box<dyn Iterator<Item = T>>` or `impl Iterator<Item = T>`, which are returned as a
+/// closure of `fn() -> IteratorItem<T>`.
+fn parse_closure(ty: &syn::Type) -> Option<Closure> {
+    let bounds = match *ty {
+        syn::Type::ImplTrait(ref impl_trait) => &impl_trait.bounds,
+        syn::Type::Path(ref path) => {
+            let segment = path.path.segments.last()?.into_value();
+            let arg = match segment.arguments {
+                syn::PathArguments::AngleBracketed(ref arguments)
+                    if segment.ident.as_ref() == "Box" =>
+                {
+                    arguments.args.first()?.into_value()
+                }
+                _ => return None,
+            };
+            match *arg {
+                syn::GenericArgument::Type(syn::Type::TraitObject(ref object)) => &object.bounds,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    if let Some(item) = iterator_item(bounds) {
+        return Some(Closure {
+            typ: quote! { c_marshalling::Closure<fn() -> c_marshalling::IteratorItem<#item>> },
+            args: vec![],
+            iterator: true,
+        });
+    }
+    let arguments = signature(bounds, &["Fn"]).expect("Returned closures can only be Fn");
+    let args = signature_args(arguments);
+    let ret = match arguments.output {
+        syn::ReturnType::Default => quote! { () },
+        syn::ReturnType::Type(_, ref ty) => quote! { #ty },
+    };
+    let owned = args.iter().map(|(_, owned, _)| owned);
+    Some(Closure {
+        typ: quote! { c_marshalling::Closure<fn(#(#owned),*) -> #ret> },
+        args: args
+            .iter()
+            .map(|&(_, ref owned, is_reference)| (owned.clone(), is_reference))
+            .collect(),
+        iterator: false,
+    })
+}
+
+/// The type of an `impl` block whose public functions are exported.
+#[derive(Clone)]
+pub struct Owner {
+    /// The last segment of the type's path, which names it in Lua.
+    pub ident: syn::Ident,
+    pub typ: quote::Tokens,
 }
 
 pub struct Function {
     pub ident: syn::Ident,
+    /// The type whose `impl` block declares the function, if any.
+    pub owner: Option<Owner>,
     pub args: Vec<Argument>,
-    pub ret: quote::Tokens,
+    /// `None` if the function returns `()`.
+    pub ret: Option<quote::Tokens>,
+    /// The returned closure, if the function returns one. `ret` is then its marshalled type.
+    pub closure: Option<Closure>,
+}
+
+impl Function {
+    /// The name of the exported C function, `Type__function` for functions in `impl` blocks.
+    pub fn symbol(&self) -> String {
+        match self.owner {
+            Some(ref owner) => format!("{}__{}", owner.ident, self.ident),
+            None => self.ident.to_string(),
+        }
+    }
+
+    /// The name of the function in the Lua module, `Type.function` for functions in `impl` blocks.
+    pub fn lua_name(&self) -> String {
+        match self.owner {
+            Some(ref owner) => format!("{}.{}", owner.ident, self.ident),
+            None => self.ident.to_string(),
+        }
+    }
+
+    /// The path of the Rust function, relative to the `extern_ffi` module's parent.
+    pub fn path(&self) -> quote::Tokens {
+        let ident = &self.ident;
+        match self.owner {
+            Some(ref owner) => {
+                let typ = &owner.typ;
+                quote! { <#typ>::#ident }
+            }
+            None => quote! { super::extern_ffi::#ident },
+        }
+    }
+}
+
+/// Replaces `Self` in the signatures of functions in `impl` blocks, since the generated
+/// functions are not declared in one.
+fn replace_self(ty: &syn::Type, owner: Option<&Owner>) -> syn::Type {
+    match owner {
+        Some(owner) => {
+            let typ = owner.typ.to_string();
+            let ty = quote! { #ty }.to_string();
+            let ty: Vec<_> = ty
+                .split_whitespace()
+                .map(|token| if token == "Self" { &typ[..] } else { token })
+                .collect();
+            syn::parse_str(&ty.join(" ")).unwrap()
+        }
+        None => ty.clone(),
+    }
+}
+
+/// The full paths of the names imported by the `use` items of `items`, e.g. `Path` for
+/// `use std::path::Path;` or `path` for `use std::path::{self, Path};`. Glob imports are
+/// not resolved.
+fn imports(items: &[syn::Item]) -> std::collections::HashMap<String, Vec<String>> {
+    let mut imports = std::collections::HashMap::new();
+    for item in items {
+        let item_use = match *item {
+            syn::Item::Use(ref item_use) => item_use,
+            _ => continue,
+        };
+        let prefix: Vec<String> = item_use
+            .prefix
+            .iter()
+            .map(|ident| ident.to_string())
+            .collect();
+        let paths: Vec<&syn::UsePath> = match item_use.tree {
+            syn::UseTree::Path(ref path) => vec![path],
+            syn::UseTree::List(ref list) => list
+                .items
+                .iter()
+                .filter_map(|tree| match *tree {
+                    syn::UseTree::Path(ref path) => Some(path),
+                    _ => None,
+                })
+                .collect(),
+            syn::UseTree::Glob(_) => vec![],
+        };
+        for path in paths {
+            let mut full_path = prefix.clone();
+            if path.ident.as_ref() != "self" {
+                full_path.push(path.ident.to_string());
+            }
+            let name = match path.rename {
+                Some((_, ref rename)) => rename.to_string(),
+                None => full_path.last().cloned().unwrap_or_default(),
+            };
+            imports.insert(name, full_path);
+        }
+    }
+    imports
+}
+
+/// Whether `path` is `str`, `std::path::Path` or `std::ffi::OsStr`, which are marshalled as
+/// the reference itself. Its first segment is resolved through `imports`, so `Path` is
+/// `std::path::Path` after `use std::path::Path;`. Other types of the same name, such as
+/// `api::Path`, are not.
+fn is_unsized(
+    path: &syn::TypePath,
+    imports: &std::collections::HashMap<String, Vec<String>>,
+) -> bool {
+    let mut segments: Vec<String> = path
+        .path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    if path.path.leading_colon.is_none() {
+        if let Some(import) = imports.get(&segments[0]) {
+            segments.splice(..1, import.iter().cloned());
+        }
+    }
+    path.qself.is_none()
+        && [
+            &["str"][..],
+            &["std", "path", "Path"],
+            &["std", "ffi", "OsStr"],
+        ]
+        .iter()
+        .any(|unsized_path| unsized_path[..] == segments[..])
 }
 
 pub fn functions(items: &[::syn::Item]) -> Vec<Function> {
+    let imports = imports(items);
     items
         .iter()
-        .filter_map(|item| {
-            if let syn::Item::Fn(ref fn_decl) = *item {
-                Some((&fn_decl.ident, &fn_decl.decl.inputs, &fn_decl.decl.output))
-            } else {
-                None
+        .flat_map(|item| match *item {
+            syn::Item::Fn(ref fn_decl) => vec![(
+                None,
+                &fn_decl.ident,
+                &fn_decl.decl.inputs,
+                &fn_decl.decl.output,
+            )],
+            // Trait implementations are not exported.
+            syn::Item::Impl(ref item_impl) if item_impl.trait_.is_none() => {
+                if !item_impl.generics.params.is_empty() {
+                    panic!("Generic impl blocks are not supported");
+                }
+                let self_ty = &item_impl.self_ty;
+                let owner = match **self_ty {
+                    syn::Type::Path(ref path) if path.qself.is_none() => Owner {
+                        ident: path.path.segments.last().unwrap().value().ident,
+                        typ: quote! { #self_ty },
+                    },
+                    _ => panic!("impl blocks are only supported for named types"),
+                };
+                item_impl
+                    .items
+                    .iter()
+                    .filter_map(|item| match *item {
+                        syn::ImplItem::Method(ref method) => match method.vis {
+                            syn::Visibility::Public(_) => Some((
+                                Some(owner.clone()),
+                                &method.sig.ident,
+                                &method.sig.decl.inputs,
+                                &method.sig.decl.output,
+                            )),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect()
             }
+            _ => vec![],
         })
-        .map(|(ident, args, output)| {
+        .map(|(owner, ident, args, output)| {
             let args: Vec<_> = args
                 .iter()
                 .map(|arg| {
                     let (name, ty_arg) = match *arg {
+                        syn::FnArg::SelfRef(ref receiver) => {
+                            return Argument {
+                                ident: syn::Ident::from("__self"),
+                                typ: owner.as_ref().unwrap().typ.clone(),
+                                slice_element: None,
+                                referent: None,
+                                mutable: receiver.mutability.is_some(),
+                                callback: None,
+                                receiver: true,
+                            };
+                        }
+                        syn::FnArg::SelfValue(_) => {
+                            return Argument {
+                                ident: syn::Ident::from("__self"),
+                                typ: owner.as_ref().unwrap().typ.clone(),
+                                slice_element: None,
+                                referent: None,
+                                mutable: false,
+                                callback: None,
+                                receiver: false,
+                            };
+                        }
                         syn::FnArg::Captured(ref cap) => match cap.pat {
-                            syn::Pat::Ident(ref pat) => (&pat.ident, &cap.ty),
+                            syn::Pat::Ident(ref pat) => {
+                                (&pat.ident, replace_self(&cap.ty, owner.as_ref()))
+                            }
                             _ => panic!("Unknown identifier"),
                         },
                         _ => panic!("Unknown identifier"),
                     };
-                    let typ = match *ty_arg {
+                    let mut slice_element = None;
+                    let mut referent = None;
+                    let mut mutable = false;
+                    let mut callback = None;
+                    let typ = match ty_arg {
+                        syn::Type::Reference(::syn::TypeReference {
+                            elem: ref ty,
+                            mutability: Some(_),
+                            ..
+                        }) => match **ty {
+                            syn::Type::Path(ref path) => {
+                                referent = Some(quote! { #path });
+                                mutable = true;
+                                quote! { &mut #path }
+                            }
+                            syn::Type::TraitObject(ref object) => {
+                                callback = Some(parse_callback(&object.bounds, quote! { &mut }));
+                                quote! { &mut #object }
+                            }
+                            _ => panic!(
+                                "Reference: Mutable function arguments can only be handles \
+                                 or callbacks"
+                            ),
+                        },
                         syn::Type::Reference(::syn::TypeReference {
                             elem: ref ty,
                             mutability: None,
                             ..
                         }) => match **ty {
                             syn::Type::Path(ref path) => {
+                                if !is_unsized(path, &imports) {
+                                    referent = Some(quote! { #path });
+                                }
                                 quote! { &#path }
                             }
-                            syn::Type::Slice(ref ty) => {
-                                if let syn::Type::Path(ref path) = *ty.elem {
-                                    quote! { &[#path] }
-                                } else {
-                                    panic!(
-                                        "Slice: Function arguments can only be immutable \
-                                         reference or immediate"
-                                    )
+                            syn::Type::Slice(ref ty) => match *ty.elem {
+                                syn::Type::Path(_)
+                                | syn::Type::Reference(::syn::TypeReference {
+                                    mutability: None,
+                                    ..
+                                }) => {
+                                    let elem = &ty.elem;
+                                    slice_element = Some(quote! { #elem });
+                                    quote! { &[#elem] }
                                 }
+                                _ => panic!(
+                                    "Slice: Function arguments can only be immutable \
+                                     reference or immediate"
+                                ),
+                            },
+                            syn::Type::TraitObject(ref object) => {
+                                callback = Some(parse_callback(&object.bounds, quote! { & }));
+                                quote! { &#object }
                             }
                             _ => panic!(
                                 "Reference: Function arguments can only be immutable \
                                  reference or immediate"
                             ),
                         },
+                        syn::Type::ImplTrait(ref impl_trait) => {
+                            callback = Some(parse_callback(&impl_trait.bounds, quote! {}));
+                            quote! { #impl_trait }
+                        }
                         ::syn::Type::Path(ref path) => {
                             quote! { #path }
                         }
+                        syn::Type::Tuple(ref tuple) => {
+                            quote! { #tuple }
+                        }
+                        syn::Type::Array(ref array) => {
+                            quote! { #array }
+                        }
                         _ => panic!(
                             "Function arguments can only be immutable reference or immediate"
                         ),
                     };
-                    Argument { ident: *name, typ }
+                    Argument {
+                        ident: *name,
+                        typ,
+                        slice_element,
+                        referent,
+                        mutable,
+                        callback,
+                        receiver: false,
+                    }
                 })
                 .collect();
+            let ret = match *output {
+                syn::ReturnType::Default => None,
+                syn::ReturnType::Type(_, ref ty) => Some(replace_self(ty, owner.as_ref())),
+            };
+            let closure = ret.as_ref().and_then(parse_closure);
             Function {
                 ident: *ident,
                 args,
-                ret: match *output {
-                    syn::ReturnType::Default => quote! { () },
-                    syn::ReturnType::Type(_, ref ty) => {
-                        if let syn::Type::Path(ref path) = **ty {
-                            quote! { #path }
-                        } else {
-                            panic!("Function return type can only be immediate")
-                        }
+                ret: match ret {
+                    None => None,
+                    Some(_) if closure.is_some() => {
+                        closure.as_ref().map(|closure| closure.typ.clone())
                     }
+                    Some(ty) => match ty {
+                        syn::Type::Path(ref path) => Some(quote! { #path }),
+                        syn::Type::Tuple(ref tuple) if tuple.elems.is_empty() => None,
+                        syn::Type::Tuple(ref tuple) => Some(quote! { #tuple }),
+                        syn::Type::Array(ref array) => Some(quote! { #array }),
+                        _ => panic!("Function return type can only be immediate"),
+                    },
                 },
+                closure,
+                owner,
             }
         })
         .collect()
 }
 
+/// Wraps the returned closure in a `c_marshalling::Closure`, which takes owned arguments.
+/// Returned iterators are dropped as soon as they are exhausted.
+fn closure_wrapping(call: quote::Tokens, closure: &Closure) -> quote::Tokens {
+    let typ = &closure.typ;
+    if closure.iterator {
+        return quote! {
+            {
+                let __iterator = std::cell::RefCell::new(Some(#call));
+                <#typ>::new(move || {
+                    let mut iterator = __iterator.borrow_mut();
+                    let item = iterator.as_mut().and_then(Iterator::next);
+                    if item.is_none() {
+                        *iterator = None;
+                    }
+                    c_marshalling::IteratorItem(item)
+                })
+            }
+        };
+    }
+    let names: Vec<_> = (0..closure.args.len())
+        .map(|index| syn::Ident::from(format!("__arg{}", index)))
+        .collect();
+    let params = names
+        .iter()
+        .zip(&closure.args)
+        .map(|(name, (owned, _))| quote! { #name: #owned });
+    let values = names
+        .iter()
+        .zip(&closure.args)
+        .map(|(name, &(_, is_reference))| {
+            if is_reference {
+                quote! { std::borrow::Borrow::borrow(&#name) }
+            } else {
+                quote! { #name }
+            }
+        });
+    quote! {
+        {
+            let __closure = #call;
+            <#typ>::new(move |#(#params),*| __closure(#(#values),*))
+        }
+    }
+}
+
+/// Wraps the callback in a closure of the declared type. Errors raised by the callback are
+/// returned if it is fallible. Otherwise they unwind, without a panic message, to the calling
+/// function, which fails with the error.
+fn callback_passing(ident: &syn::Ident, callback: &Callback) -> quote::Tokens {
+    let typ = &callback.typ;
+    let ret = &callback.ret;
+    let borrow = &callback.borrow;
+    let names: Vec<_> = (0..callback.args.len())
+        .map(|index| syn::Ident::from(format!("__arg{}", index)))
+        .collect();
+    let params = names
+        .iter()
+        .zip(&callback.args)
+        .map(|(name, (declared, _))| quote! { #name: #declared });
+    let values = names
+        .iter()
+        .zip(&callback.args)
+        .map(|(name, &(_, is_reference))| {
+            if is_reference {
+                quote! { std::borrow::ToOwned::to_owned(#name) }
+            } else {
+                quote! { #name }
+            }
+        });
+    let handling = if callback.fallible {
+        quote! { .map_err(From::from) }
+    } else {
+        quote! { .unwrap_or_else(|err| std::panic::resume_unwind(Box::new(err))) }
+    };
+    quote! {
+        #borrow move |#(#params),*| -> #ret {
+            <#typ as c_marshalling::Callback>::call(#ident, (#(#values,)*))#handling
+        }
+    }
+}
+
 pub fn function_declarations(functions: &[Function], uses: &[quote::Tokens]) -> quote::Tokens {
     let extern_c_ffi_functions = functions.iter().map(|function| {
         let argument_declaration = function.args.iter().map(|arg| {
             let ident = &arg.ident;
-            let typ = &arg.typ;
-            quote! { #ident: <#typ as c_marshalling::PtrAsReference>::Ptr }
+            let typ = arg.marshalled_typ();
+            if arg.receiver {
+                return quote! { #ident: *const std::ffi::c_void };
+            }
+            match (&arg.slice_element, &arg.referent) {
+                (Some(elem), _) => quote! {
+                    #ident: *const c_marshalling::CSlice<
+                        <#elem as c_marshalling::SliceAsReference>::Raw>
+                },
+                (None, Some(_)) => quote! { #ident: <#typ as c_marshalling::RefArgument>::Ptr },
+                (None, None) if arg.callback.is_some() => {
+                    quote! { #ident: <#typ as c_marshalling::Callback>::Ptr }
+                }
+                (None, None) => quote! { #ident: <#typ as c_marshalling::PtrAsReference>::Ptr },
+            }
         });
         let argument_passing = function.args.iter().map(|arg| {
             let ident = &arg.ident;
             let typ = &arg.typ;
-            quote! {
-                <#typ as c_marshalling::PtrAsReference>::ptr_as_ref(#ident)?
+            if let Some(ref callback) = arg.callback {
+                return callback_passing(ident, callback);
+            }
+            if arg.receiver {
+                return if arg.mutable {
+                    quote! {
+                        &mut *(&c_marshalling::Receiver::<#typ>::new()).receive_mut(#ident)?
+                    }
+                } else {
+                    quote! { &*(&c_marshalling::Receiver::<#typ>::new()).receive(#ident)? }
+                };
+            }
+            match (&arg.slice_element, &arg.referent) {
+                (Some(elem), _) => quote! {
+                    &<#elem as c_marshalling::SliceAsReference>::ptr_as_slice(#ident)?
+                },
+                (None, Some(referent)) if arg.mutable => quote! {
+                    &mut *<#referent as c_marshalling::MutArgument>::ptr_as_deref_mut(#ident)?
+                },
+                (None, Some(referent)) => quote! {
+                    &*<#referent as c_marshalling::RefArgument>::ptr_as_deref(#ident)?
+                },
+                (None, None) => quote! {
+                    <#typ as c_marshalling::PtrAsReference>::ptr_as_ref(#ident)?
+                },
             }
         });
-        let gc_ident = syn::parse_str::<syn::Path>(&format!("__gc_{}", function.ident)).unwrap();
-        let ret = &function.ret;
-        let ident = &function.ident;
+        let ident = syn::Ident::from(function.symbol());
+        let path = function.path();
+        let call = quote! { #path(#(#argument_passing),*) };
+        let call = match function.closure {
+            Some(ref closure) => closure_wrapping(call, closure),
+            None => call,
+        };
+        let ret = match function.ret {
+            Some(ref ret) => ret,
+            None => {
+                return quote! {
+                    /// # Safety
+                    ///
+                    /// Only called in an auto-generated context. Should not be called directly.
+                    #[no_mangle]
+                    pub unsafe extern "C" fn #ident(#(#argument_declaration),*) -> u32 {
+                        // Handles may be left inconsistent by a panic, like any other `&mut`.
+                        std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+                            || -> Result<u32, c_marshalling::Error> {
+                                #call;
+                                Ok(0)
+                            },
+                        )).unwrap_or_else(|payload| Ok(c_marshalling::unwind_status(payload)))
+                        .unwrap_or_else(c_marshalling::error_status)
+                    }
+                };
+            }
+        };
+        let gc_ident = syn::parse_str::<syn::Path>(&format!("__gc_{}", function.symbol())).unwrap();
         quote! {
                 /// # Safety
                 ///
@@ -134,12 +735,16 @@ pub fn function_declarations(functions: &[Function], uses: &[quote::Tokens]) ->
                 pub unsafe extern "C" fn #ident(
                         #(#argument_declaration,)*
                         __output: *mut <#ret as c_marshalling::IntoRawConversion>::Ptr) -> u32 {
-                    std::panic::catch_unwind(|| -> Result<u32, c_marshalling::Error> {
-                        *__output = <#ret as c_marshalling::IntoRawConversion >::into_ptr(
-                            super::extern_ffi::#ident(#(#argument_passing),*)
-                        )?;
-                        Ok(0)
-                    }).unwrap_or(Ok(2)).unwrap_or(1)
+                    // Handles may be left inconsistent by a panic, like any other `&mut`.
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+                        || -> Result<u32, c_marshalling::Error> {
+                            *__output = <#ret as c_marshalling::IntoRawConversion >::into_ptr(
+                                #call
+                            )?;
+                            Ok(0)
+                        },
+                    )).unwrap_or_else(|payload| Ok(c_marshalling::unwind_status(payload)))
+                    .unwrap_or_else(c_marshalling::error_status)
                 }
 
                 /// # Safety
@@ -154,10 +759,33 @@ pub fn function_declarations(functions: &[Function], uses: &[quote::Tokens]) ->
         }
     });
 
+    let receivers = if functions
+        .iter()
+        .any(|function| function.args.iter().any(|arg| arg.receiver))
+    {
+        // Only one of them is used if all receivers are of the same kind.
+        quote! {
+            #[allow(unused_imports)]
+            use c_marshalling::{HandleReceiver as _, ValueReceiver as _};
+        }
+    } else {
+        quote! {}
+    };
+
     quote! {
         #[doc(hidden)]
+        // Callback types are spelled out, errors of callbacks returning `c_marshalling::Error`
+        // are converted into it, returned closures are wrapped even if their arguments need
+        // no conversion and receivers are borrowed explicitly to pick their kind.
+        #[allow(
+            clippy::type_complexity,
+            clippy::useless_conversion,
+            clippy::redundant_closure,
+            clippy::needless_borrow
+        )]
         pub mod extern_c_ffi {
             #(#uses)*
+            #receivers
 
             #(#extern_c_ffi_functions) *
         }
@@ -103,17 +103,14 @@ pub mod extern_ffi {
         3
     }
 
-    // Note: No support for return ! or ()
+    // Note: No support for return !
     pub fn i_like_to_panic() -> i32 {
         panic!("p-p-p-p-p-anic!");
     }
 
     pub fn make_f(a: Option<A>) -> F {
         F {
-            thing: match a {
-                Some(A { string, integer }) => Some(vec![vec![A { string, integer }]]),
-                None => None,
-            },
+            thing: a.map(|A { string, integer }| vec![vec![A { string, integer }]]),
         }
     }
 
@@ -1,4 +1,5 @@
 use lua_marshalling::LuaMarshalling;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 #[derive(Clone, Debug, Eq, PartialEq, LuaMarshalling)]
 pub struct A {
@@ -42,9 +43,238 @@ pub struct G {
     vec_b: Vec<bool>,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, LuaMarshalling)]
+pub enum Color {
+    Red,
+    Green,
+    Blue = 4,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, LuaMarshalling)]
+#[lua(integer)]
+pub enum Level {
+    Low = 1,
+    High = 10,
+}
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct H {
+    color: Color,
+    option_color: Option<Color>,
+    colors: Vec<Color>,
+    level: Level,
+}
+
+#[derive(Clone, Debug, PartialEq, LuaMarshalling)]
+pub enum Shape {
+    Circle { radius: f64 },
+    Rectangle { width: f64, height: f64 },
+    Point,
+}
+
+// Named like the payload of `Shape::Circle`.
+#[allow(non_camel_case_types)]
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Shape_Circle {
+    pub label: String,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub enum Event {
+    Key(u32),
+    Text(String, Option<A>),
+    Shapes { shapes: Vec<Shape> },
+    Color(Color),
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, LuaMarshalling)]
+pub struct Meters(f64);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct UserId(u32);
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct Name(String);
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct WrappedA(A);
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct Pair(i32, String);
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct Ack;
+
+#[derive(Debug, Eq, PartialEq, LuaMarshalling)]
+pub struct I {
+    id: UserId,
+    name: Name,
+    pair: Pair,
+    ids: Vec<UserId>,
+    names: Option<Vec<Name>>,
+    ack: Option<Ack>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct J {
+    point: (f64, f64),
+    labels: Vec<(String, Option<A>)>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct K {
+    members: HashMap<String, A>,
+    groups: BTreeMap<u32, Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct L {
+    tags: HashSet<String>,
+    ids: BTreeSet<i64>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Node {
+    name: String,
+    children: Vec<Node>,
+    next: Option<Box<Node>>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub enum Expression {
+    Number(f64),
+    Negate(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Sum { terms: Vec<Expression> },
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Labelled {
+    children: Vec<(String, Labelled)>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Directory {
+    name: String,
+    files: Vec<File>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct File {
+    name: String,
+    directory: Option<Directory>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Readings {
+    samples: Vec<Option<f64>>,
+    labels: Vec<Option<String>>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Outcome {
+    result: Result<i32, String>,
+    results: Vec<Result<Option<String>, Option<i32>>>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Scalars {
+    letter: char,
+    large: i128,
+    natural: u128,
+    count: std::num::NonZeroU32,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Image {
+    name: String,
+    pixels: c_marshalling::Bytes,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Interned {
+    name: std::sync::Arc<str>,
+    label: std::borrow::Cow<'static, str>,
+    boxed: Box<str>,
+    shared: std::rc::Rc<str>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Pixel {
+    position: [f32; 3],
+    color: [u8; 4],
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Page<T> {
+    items: Vec<T>,
+    next: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Couple<T, U>(T, U);
+
+#[derive(Debug, PartialEq, LuaMarshalling)]
+pub struct Tagged<T>(T);
+
+pub mod v1 {
+    use lua_marshalling::LuaMarshalling;
+
+    #[derive(Debug, PartialEq, LuaMarshalling)]
+    pub struct Request {
+        pub path: String,
+    }
+}
+
+// Shares its name with `std::path::Path`.
+pub mod api {
+    use lua_marshalling::LuaMarshalling;
+
+    #[derive(Debug, PartialEq, LuaMarshalling)]
+    pub struct Path {
+        pub segments: Vec<String>,
+    }
+}
+
+pub mod v2 {
+    use lua_marshalling::LuaMarshalling;
+
+    #[derive(Debug, PartialEq, LuaMarshalling)]
+    pub struct Request {
+        pub path: String,
+        pub retries: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, LuaMarshalling)]
+    pub enum Method {
+        Get,
+        Post,
+    }
+}
+
+/// Stays in Rust and is handed to Lua as a `Handle`, caching the words it has looked up.
+pub struct Index {
+    words: Vec<String>,
+    cache: std::cell::RefCell<HashMap<String, Option<usize>>>,
+}
+
+impl Drop for Index {
+    fn drop(&mut self) {
+        DROPPED_INDICES.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+static DROPPED_INDICES: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+static COUNTER: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
 pub mod extern_ffi {
     // Intentionally not `use` all structs to test relative names
     use super::{A, D};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::ffi::OsStr as NativeStr;
+    use std::path::{self, Path};
 
     pub fn square_i8(value: i8) -> i8 {
         value * value
@@ -182,7 +412,7 @@ pub mod extern_ffi {
         )
     }
 
-    // Note: No support for return ! or ()
+    // Note: No support for return !
     pub fn i_like_to_panic() -> i32 {
         panic!("p-p-p-p-p-anic!");
     }
@@ -223,6 +453,828 @@ pub mod extern_ffi {
     pub fn err_none() -> Result<String, Option<i32>> {
         Err(None)
     }
+
+    pub fn next_color(color: super::Color) -> super::Color {
+        use super::Color;
+        match color {
+            Color::Red => Color::Green,
+            Color::Green => Color::Blue,
+            Color::Blue => Color::Red,
+        }
+    }
+
+    pub fn toggle_level(level: super::Level) -> super::Level {
+        use super::Level;
+        match level {
+            Level::Low => Level::High,
+            Level::High => Level::Low,
+        }
+    }
+
+    pub fn make_h(
+        color: super::Color,
+        option_color: Option<super::Color>,
+        colors: Vec<super::Color>,
+        level: super::Level,
+    ) -> super::H {
+        super::H {
+            color,
+            option_color,
+            colors,
+            level,
+        }
+    }
+
+    pub fn describe_h(h: super::H) -> String {
+        format!("{:?}", h)
+    }
+
+    pub fn area(shape: super::Shape) -> f64 {
+        use super::Shape;
+        match shape {
+            Shape::Circle { radius } => std::f64::consts::PI * radius * radius,
+            Shape::Rectangle { width, height } => width * height,
+            Shape::Point => 0.0,
+        }
+    }
+
+    pub fn scale_shape(shape: super::Shape, factor: f64) -> super::Shape {
+        use super::Shape;
+        match shape {
+            Shape::Circle { radius } => Shape::Circle {
+                radius: radius * factor,
+            },
+            Shape::Rectangle { width, height } => Shape::Rectangle {
+                width: width * factor,
+                height: height * factor,
+            },
+            Shape::Point => Shape::Point,
+        }
+    }
+
+    pub fn label_circle(shape: super::Shape, label: String) -> super::Shape_Circle {
+        super::Shape_Circle {
+            label: format!("{} {}", label, area(shape)),
+        }
+    }
+
+    pub fn echo_events(events: Vec<super::Event>) -> Vec<super::Event> {
+        events
+    }
+
+    pub fn describe_event(event: super::Event) -> String {
+        format!("{:?}", event)
+    }
+
+    pub fn add_meters(a: super::Meters, b: super::Meters) -> super::Meters {
+        super::Meters(a.0 + b.0)
+    }
+
+    pub fn next_user_id(id: super::UserId) -> super::UserId {
+        super::UserId(id.0 + 1)
+    }
+
+    pub fn greet(name: super::Name) -> super::Name {
+        super::Name(format!("Hello {}!", name.0))
+    }
+
+    pub fn wrap_a(a: A) -> super::WrappedA {
+        super::WrappedA(a)
+    }
+
+    pub fn unwrap_a(wrapped: super::WrappedA) -> A {
+        wrapped.0
+    }
+
+    pub fn make_pair(integer: i32, string: &str) -> super::Pair {
+        super::Pair(integer, string.to_owned())
+    }
+
+    pub fn describe_pair(pair: super::Pair) -> String {
+        format!("{:?}", pair)
+    }
+
+    pub fn maybe_acknowledge(acknowledge: bool) -> Option<super::Ack> {
+        if acknowledge {
+            Some(super::Ack)
+        } else {
+            None
+        }
+    }
+
+    pub fn count_acks(acks: Vec<super::Ack>) -> u32 {
+        acks.len() as u32
+    }
+
+    pub fn make_i(
+        id: super::UserId,
+        name: super::Name,
+        pair: super::Pair,
+        ids: Vec<super::UserId>,
+        names: Option<Vec<super::Name>>,
+        ack: Option<super::Ack>,
+    ) -> super::I {
+        super::I {
+            id,
+            name,
+            pair,
+            ids,
+            names,
+            ack,
+        }
+    }
+
+    pub fn describe_i(i: super::I) -> String {
+        format!("{:?}", i)
+    }
+
+    pub fn divide(dividend: i32, divisor: i32) -> (i32, i32) {
+        (dividend / divisor, dividend % divisor)
+    }
+
+    pub fn split_a(a: A) -> (String, i32, bool) {
+        (a.string, a.integer, true)
+    }
+
+    pub fn swap(pair: (i32, String)) -> (String, i32) {
+        (pair.1, pair.0)
+    }
+
+    pub fn nest(nested: (i32, (String, bool))) -> ((i32, String), bool) {
+        let (integer, (string, boolean)) = nested;
+        ((integer, string), boolean)
+    }
+
+    pub fn zip(integers: Vec<i32>, strings: Vec<String>) -> Vec<(i32, String)> {
+        integers.into_iter().zip(strings).collect()
+    }
+
+    pub fn make_j(point: (f64, f64), labels: Vec<(String, Option<A>)>) -> super::J {
+        super::J { point, labels }
+    }
+
+    pub fn describe_j(j: super::J) -> String {
+        format!("{:?}", j)
+    }
+
+    pub fn reset_counter() {
+        super::COUNTER.store(0, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn add_to_counter(value: u32) {
+        super::COUNTER.fetch_add(value, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn counter() -> u32 {
+        super::COUNTER.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn add_even_to_counter(value: u32) -> Result<(), String> {
+        if value.is_multiple_of(2) {
+            add_to_counter(value);
+            Ok(())
+        } else {
+            Err(format!("{} is odd", value))
+        }
+    }
+
+    pub fn panic_without_return() {
+        panic!("p-p-p-p-p-anic!");
+    }
+
+    pub fn units(units: Vec<()>) -> Option<()> {
+        units.first().cloned()
+    }
+
+    pub fn count_words(text: &str) -> HashMap<String, u32> {
+        let mut counts = HashMap::new();
+        for word in text.split_whitespace() {
+            *counts.entry(word.to_owned()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn sum_values(values: HashMap<String, i32>) -> i32 {
+        values.values().sum()
+    }
+
+    pub fn squares(count: i64) -> BTreeMap<i64, i32> {
+        (1..=count).map(|i| (i, (i * i) as i32)).collect()
+    }
+
+    pub fn key_indices(keys: Vec<u64>) -> HashMap<u64, u32> {
+        keys.into_iter().zip(0..).collect()
+    }
+
+    pub fn describe_u64_set(set: BTreeSet<u64>) -> String {
+        format!("{:?}", set)
+    }
+
+    pub fn describe_btree_map(map: BTreeMap<i32, String>) -> String {
+        format!("{:?}", map)
+    }
+
+    pub fn make_k(members: HashMap<String, A>, groups: BTreeMap<u32, Vec<String>>) -> super::K {
+        super::K { members, groups }
+    }
+
+    pub fn describe_k(k: super::K) -> String {
+        let members: BTreeMap<_, _> = k.members.into_iter().collect();
+        format!("members: {:?}, groups: {:?}", members, k.groups)
+    }
+
+    pub fn split_tags(text: &str) -> HashSet<String> {
+        text.split(',').map(str::trim).map(str::to_owned).collect()
+    }
+
+    pub fn describe_btree_set(set: BTreeSet<i32>) -> String {
+        format!("{:?}", set)
+    }
+
+    pub fn describe_bool_set(set: BTreeSet<bool>) -> String {
+        format!("{:?}", set)
+    }
+
+    pub fn make_l(tags: HashSet<String>, ids: BTreeSet<i64>) -> super::L {
+        super::L { tags, ids }
+    }
+
+    pub fn describe_l(l: super::L) -> String {
+        let tags: BTreeSet<_> = l.tags.into_iter().collect();
+        format!("tags: {:?}, ids: {:?}", tags, l.ids)
+    }
+
+    pub fn make_tree(name: String, depth: u32) -> super::Node {
+        super::Node {
+            children: (0..depth)
+                .map(|i| make_tree(format!("{}.{}", name, i), i))
+                .collect(),
+            next: None,
+            name,
+        }
+    }
+
+    pub fn make_list(names: Vec<String>) -> Option<Box<super::Node>> {
+        names.into_iter().rev().fold(None, |next, name| {
+            Some(Box::new(super::Node {
+                name,
+                children: Vec::new(),
+                next,
+            }))
+        })
+    }
+
+    pub fn count_nodes(node: super::Node) -> u32 {
+        1 + node.children.into_iter().map(count_nodes).sum::<u32>()
+            + node.next.map_or(0, |next| count_nodes(*next))
+    }
+
+    pub fn evaluate(expression: super::Expression) -> f64 {
+        match expression {
+            super::Expression::Number(number) => number,
+            super::Expression::Negate(expression) => -evaluate(*expression),
+            super::Expression::Add(left, right) => evaluate(*left) + evaluate(*right),
+            super::Expression::Sum { terms } => terms.into_iter().map(evaluate).sum(),
+        }
+    }
+
+    pub fn negate(expression: super::Expression) -> super::Expression {
+        super::Expression::Negate(Box::new(expression))
+    }
+
+    pub fn boxed_a(a: Box<A>) -> Box<A> {
+        a
+    }
+
+    pub fn count_labelled(labelled: super::Labelled) -> u32 {
+        labelled
+            .children
+            .into_iter()
+            .map(|(_, child)| 1 + count_labelled(child))
+            .sum()
+    }
+
+    pub fn describe_directory(directory: super::Directory) -> String {
+        format!("{:?}", directory)
+    }
+
+    pub fn join_strings(strings: &[String], separator: &str) -> String {
+        strings.join(separator)
+    }
+
+    pub fn join_strs(strs: &[&str]) -> String {
+        strs.concat()
+    }
+
+    pub fn sum_integers_of(a: &[A]) -> i32 {
+        a.iter().map(|a| a.integer).sum()
+    }
+
+    pub fn describe_slices(
+        colors: &[super::Color],
+        ids: &[super::UserId],
+        shapes: &[super::Shape],
+    ) -> String {
+        format!("{:?} {:?} {:?}", colors, ids, shapes)
+    }
+
+    pub fn fill_gaps(samples: Vec<Option<i32>>, value: i32) -> Vec<i32> {
+        samples
+            .into_iter()
+            .map(|sample| sample.unwrap_or(value))
+            .collect()
+    }
+
+    pub fn every_other(count: i32) -> Vec<Option<i32>> {
+        (0..count)
+            .map(|i| if i % 2 == 0 { Some(i) } else { None })
+            .collect()
+    }
+
+    pub fn echo_readings(readings: super::Readings) -> super::Readings {
+        readings
+    }
+
+    pub fn describe_readings(readings: super::Readings) -> String {
+        format!("{:?}", readings)
+    }
+
+    pub fn increment_result(result: Result<i32, String>) -> Result<i32, String> {
+        result.map(|value| value + 1)
+    }
+
+    pub fn describe_result(result: Result<A, super::B>) -> String {
+        format!("{:?}", result)
+    }
+
+    pub fn echo_outcome(outcome: super::Outcome) -> super::Outcome {
+        outcome
+    }
+
+    pub fn describe_outcome(outcome: super::Outcome) -> String {
+        format!("{:?}", outcome)
+    }
+
+    pub fn next_char(letter: char) -> char {
+        std::char::from_u32(letter as u32 + 1).unwrap_or(letter)
+    }
+
+    pub fn chars_of(string: String) -> Vec<char> {
+        string.chars().collect()
+    }
+
+    pub fn string_of(chars: Vec<char>) -> String {
+        chars.into_iter().collect()
+    }
+
+    pub fn add_i128(a: i128, b: i128) -> i128 {
+        a + b
+    }
+
+    pub fn max_u128() -> u128 {
+        u128::MAX
+    }
+
+    pub fn double_count(count: std::num::NonZeroU32) -> std::num::NonZeroU32 {
+        count.saturating_mul(std::num::NonZeroU32::new(2).unwrap())
+    }
+
+    pub fn zero_count() -> Result<std::num::NonZeroU32, String> {
+        std::num::NonZeroU32::new(0).ok_or_else(|| "zero".to_owned())
+    }
+
+    pub fn double_counts(counts: Vec<std::num::NonZeroU32>) -> Vec<std::num::NonZeroU32> {
+        counts.into_iter().map(double_count).collect()
+    }
+
+    pub fn first_count(counts: Vec<u32>) -> Option<std::num::NonZeroU32> {
+        counts.first().copied().and_then(std::num::NonZeroU32::new)
+    }
+
+    pub fn echo_scalars(scalars: super::Scalars) -> super::Scalars {
+        scalars
+    }
+
+    pub fn describe_scalars(scalars: super::Scalars) -> String {
+        format!("{:?}", scalars)
+    }
+
+    pub fn echo_string(string: String) -> String {
+        string
+    }
+
+    pub fn str_length(string: &str) -> usize {
+        string.len()
+    }
+
+    pub fn make_c_string(string: &str) -> Result<std::ffi::CString, String> {
+        std::ffi::CString::new(string).map_err(|err| err.to_string())
+    }
+
+    pub fn c_string_length(string: std::ffi::CString) -> usize {
+        string.as_bytes().len()
+    }
+
+    pub fn all_bytes() -> c_marshalling::Bytes {
+        (0..=255).collect::<Vec<u8>>().into()
+    }
+
+    pub fn reverse_bytes(mut bytes: c_marshalling::Bytes) -> c_marshalling::Bytes {
+        bytes.reverse();
+        bytes
+    }
+
+    pub fn split_bytes(bytes: &[u8], size: u32) -> Vec<c_marshalling::Bytes> {
+        bytes
+            .chunks(size as usize)
+            .map(|chunk| chunk.to_vec().into())
+            .collect()
+    }
+
+    pub fn maybe_bytes(bytes: Option<c_marshalling::Bytes>) -> Option<c_marshalling::Bytes> {
+        bytes
+    }
+
+    pub fn file_name(path: &std::path::Path) -> Option<std::ffi::OsString> {
+        path.file_name().map(std::ffi::OsStr::to_owned)
+    }
+
+    pub fn join_paths(base: std::path::PathBuf, paths: &[&std::path::Path]) -> std::path::PathBuf {
+        paths.iter().fold(base, |base, path| base.join(path))
+    }
+
+    pub fn os_str_length(string: &std::ffi::OsStr) -> u32 {
+        string.len() as u32
+    }
+
+    pub fn extension(path: &Path) -> Option<std::ffi::OsString> {
+        path.extension().map(NativeStr::to_owned)
+    }
+
+    pub fn is_absolute(path: &path::Path) -> bool {
+        path.is_absolute()
+    }
+
+    pub fn native_str_length(string: &NativeStr) -> u32 {
+        string.len() as u32
+    }
+
+    pub fn api_path_depth(path: super::api::Path) -> u32 {
+        path.segments.len() as u32
+    }
+
+    pub fn echo_os_string(string: std::ffi::OsString) -> std::ffi::OsString {
+        string
+    }
+
+    pub fn intern(name: &str) -> std::sync::Arc<str> {
+        name.into()
+    }
+
+    pub fn intern_all(names: Vec<std::sync::Arc<str>>) -> Vec<std::sync::Arc<str>> {
+        names
+    }
+
+    pub fn lookup_label(key: u32) -> std::borrow::Cow<'static, str> {
+        match key {
+            0 => "zero".into(),
+            key => format!("key {}", key).into(),
+        }
+    }
+
+    pub fn shout(mut string: Box<str>) -> Box<str> {
+        string.make_ascii_uppercase();
+        string
+    }
+
+    pub fn shared_length(string: std::rc::Rc<str>) -> u32 {
+        string.len() as u32
+    }
+
+    pub fn echo_interned(interned: super::Interned) -> super::Interned {
+        interned
+    }
+
+    pub fn describe_interned(interned: super::Interned) -> String {
+        format!("{:?}", interned)
+    }
+
+    pub fn scale(vector: [f32; 3], factor: f32) -> [f32; 3] {
+        vector.map(|value| value * factor)
+    }
+
+    pub fn identity_matrix() -> [[i32; 2]; 2] {
+        [[1, 0], [0, 1]]
+    }
+
+    pub fn transpose(matrix: [[i32; 2]; 2]) -> [[i32; 2]; 2] {
+        [[matrix[0][0], matrix[1][0]], [matrix[0][1], matrix[1][1]]]
+    }
+
+    pub fn join_pair(pair: [String; 2]) -> String {
+        pair.join(" & ")
+    }
+
+    pub fn maybe_pair(pair: Option<[Option<i32>; 2]>) -> Option<[Option<i32>; 2]> {
+        pair
+    }
+
+    pub fn colors(pixels: Vec<super::Pixel>) -> Vec<[u8; 4]> {
+        pixels.into_iter().map(|pixel| pixel.color).collect()
+    }
+
+    pub fn echo_pixel(pixel: super::Pixel) -> super::Pixel {
+        pixel
+    }
+
+    pub fn first_page_of_a(items: Vec<A>) -> super::Page<A> {
+        super::Page {
+            items,
+            next: Some(2),
+        }
+    }
+
+    pub fn last_page_of_strings(items: Vec<String>) -> super::Page<String> {
+        super::Page { items, next: None }
+    }
+
+    pub fn describe_pages(a: super::Page<A>, strings: super::Page<String>) -> String {
+        format!("{:?} {:?}", a, strings)
+    }
+
+    pub fn swap_couple(couple: super::Couple<i32, String>) -> super::Couple<String, i32> {
+        super::Couple(couple.1, couple.0)
+    }
+
+    pub fn nested_pages(
+        pages: Vec<super::Page<super::Tagged<i32>>>,
+    ) -> super::Page<super::Page<super::Tagged<i32>>> {
+        super::Page {
+            items: pages,
+            next: None,
+        }
+    }
+
+    pub fn upgrade_request(
+        request: super::v1::Request,
+        method: super::v2::Method,
+    ) -> super::v2::Request {
+        super::v2::Request {
+            path: request.path,
+            retries: match method {
+                super::v2::Method::Get => Some(3),
+                super::v2::Method::Post => None,
+            },
+        }
+    }
+
+    pub fn downgrade_requests(requests: Vec<super::v2::Request>) -> Vec<super::v1::Request> {
+        requests
+            .into_iter()
+            .map(|request| super::v1::Request { path: request.path })
+            .collect()
+    }
+
+    pub fn new_index(words: Vec<String>) -> c_marshalling::Handle<super::Index> {
+        c_marshalling::Handle::new(super::Index {
+            words,
+            cache: Default::default(),
+        })
+    }
+
+    pub fn try_new_index(
+        words: Vec<String>,
+    ) -> Result<c_marshalling::Handle<super::Index>, String> {
+        if words.is_empty() {
+            Err("An index needs words".to_owned())
+        } else {
+            Ok(new_index(words))
+        }
+    }
+
+    pub fn split_index(words: Vec<String>) -> Vec<c_marshalling::Handle<super::Index>> {
+        words
+            .into_iter()
+            .map(|word| new_index(vec![word]))
+            .collect()
+    }
+
+    pub fn index_position(
+        index: &c_marshalling::Handle<super::Index>,
+        word: String,
+    ) -> Option<usize> {
+        *index
+            .cache
+            .borrow_mut()
+            .entry(word)
+            .or_insert_with_key(|word| index.words.iter().position(|w| w == word))
+    }
+
+    pub fn index_cached(index: &c_marshalling::Handle<super::Index>) -> usize {
+        index.cache.borrow().len()
+    }
+
+    pub fn index_insert(index: &mut c_marshalling::Handle<super::Index>, word: String) {
+        index.cache.get_mut().remove(&word);
+        index.words.push(word);
+    }
+
+    pub fn swap_index(
+        index: &mut c_marshalling::Handle<super::Index>,
+        words: Vec<String>,
+    ) -> c_marshalling::Handle<super::Index> {
+        std::mem::replace(index, new_index(words))
+    }
+
+    pub fn index_equals(
+        index: &c_marshalling::Handle<super::Index>,
+        other: &c_marshalling::Handle<super::Index>,
+    ) -> bool {
+        index.words == other.words
+    }
+
+    pub fn dropped_indices() -> u32 {
+        super::DROPPED_INDICES.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn filter_integers(values: Vec<i32>, predicate: &dyn Fn(i32) -> bool) -> Vec<i32> {
+        values
+            .into_iter()
+            .filter(|&value| predicate(value))
+            .collect()
+    }
+
+    pub fn sort_strings(
+        mut values: Vec<String>,
+        compare: impl Fn(&str, &str) -> i32,
+    ) -> Vec<String> {
+        values.sort_by(|a, b| compare(a, b).cmp(&0));
+        values
+    }
+
+    pub fn visit_a(values: Vec<A>, visitor: &mut dyn FnMut(&A)) {
+        values.iter().for_each(visitor);
+    }
+
+    pub fn build_a(make: impl FnOnce() -> A) -> A {
+        make()
+    }
+
+    pub fn try_format_integers(
+        values: Vec<i32>,
+        format: impl Fn(i32) -> Result<String, c_marshalling::Error>,
+    ) -> Result<Vec<String>, String> {
+        values
+            .into_iter()
+            .map(format)
+            .collect::<Result<_, _>>()
+            .map_err(|err| format!("Formatting failed: {}", err))
+    }
+
+    // Skips the values which fail to format, then fails to return the borrowed handle.
+    pub fn swap_formatted_index(
+        index: &mut c_marshalling::Handle<super::Index>,
+        values: Vec<i32>,
+        format: impl Fn(i32) -> Result<String, c_marshalling::Error>,
+    ) -> c_marshalling::Handle<super::Index> {
+        let words = values.into_iter().filter_map(|value| format(value).ok());
+        std::mem::replace(index, new_index(words.collect()))
+    }
+
+    pub fn prefix_matcher(prefix: String) -> Box<dyn Fn(&str) -> bool> {
+        Box::new(move |string| string.starts_with(&prefix))
+    }
+
+    pub fn greeter(greeting: A) -> impl Fn(&A, u32) -> String {
+        move |a, times| format!("{} {}", greeting.string, a.string).repeat(times as usize)
+    }
+
+    pub fn divider(divisor: i32) -> Box<dyn Fn(i32) -> Result<i32, String>> {
+        Box::new(move |value| {
+            value
+                .checked_div(divisor)
+                .ok_or_else(|| format!("Cannot divide {} by {}", value, divisor))
+        })
+    }
+
+    pub fn range_maker(len: u32) -> Box<dyn Fn() -> Vec<u32>> {
+        Box::new(move || (0..len).collect())
+    }
+
+    pub fn word_matcher(words: Vec<String>) -> Box<dyn Fn(&str) -> bool> {
+        let index = super::Index {
+            words,
+            cache: Default::default(),
+        };
+        Box::new(move |word| index.words.iter().any(|w| w == word))
+    }
+
+    pub fn panicking_closure() -> Box<dyn Fn(i32)> {
+        Box::new(|value| panic!("{} is not allowed", value))
+    }
+
+    pub fn scan(from: u32, to: u32) -> impl Iterator<Item = u32> {
+        from..to
+    }
+
+    pub fn scan_a(values: Vec<A>) -> Box<dyn Iterator<Item = A>> {
+        Box::new(values.into_iter())
+    }
+
+    pub fn scan_options(values: Vec<Option<i32>>) -> impl Iterator<Item = Option<i32>> {
+        values.into_iter()
+    }
+
+    pub fn parse_integers(strings: Vec<String>) -> impl Iterator<Item = Result<i32, String>> {
+        strings.into_iter().map(|string| {
+            string
+                .parse()
+                .map_err(|_| format!("Not an integer: {}", string))
+        })
+    }
+
+    pub fn naturals() -> impl Iterator<Item = u64> {
+        1..
+    }
+
+    pub fn index_words(words: Vec<String>) -> impl Iterator<Item = String> {
+        let index = super::Index {
+            words,
+            cache: Default::default(),
+        };
+        (0..index.words.len()).map(move |position| index.words[position].clone())
+    }
+
+    impl super::Index {
+        pub fn new(words: Vec<String>) -> c_marshalling::Handle<Self> {
+            new_index(words)
+        }
+
+        pub fn len(&self) -> usize {
+            self.words.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.words.is_empty()
+        }
+
+        pub fn words(&self) -> Vec<String> {
+            self.words.clone()
+        }
+
+        pub fn push(&mut self, word: String) {
+            self.cache.get_mut().remove(&word);
+            self.words.push(word);
+        }
+
+        pub fn merge(&mut self, other: &c_marshalling::Handle<Self>) {
+            self.words.extend(other.words.iter().cloned());
+            self.cache.get_mut().clear();
+        }
+    }
+
+    impl A {
+        pub fn with_integer(integer: i32) -> Self {
+            A {
+                string: integer.to_string(),
+                integer,
+            }
+        }
+
+        pub fn describe(self) -> String {
+            format!("{} {}", self.string, self.integer)
+        }
+
+        pub fn is_answer(&self) -> bool {
+            self.integer == 42
+        }
+
+        #[allow(dead_code)]
+        fn hidden(&self) {}
+    }
+
+    impl super::Color {
+        pub fn next(&self) -> Self {
+            use super::Color;
+            match self {
+                Color::Red => Color::Green,
+                Color::Green => Color::Blue,
+                Color::Blue => Color::Red,
+            }
+        }
+    }
+
+    pub fn invert_image(image: super::Image) -> super::Image {
+        super::Image {
+            name: image.name,
+            pixels: image
+                .pixels
+                .iter()
+                .map(|pixel| !pixel)
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
 }
 
 include!(concat!(env!("OUT_DIR"), "/ffi.rs"));